@@ -0,0 +1,18 @@
+use std::{env, path::Path};
+
+use wayland_scanner::{generate_code, Side};
+
+/// Protocols which are not available in wayland-protocols
+const PROTOCOLS: &[(&str, &str)] = &[(
+    "protocols/virtual-keyboard-unstable-v1.xml",
+    "virtual_keyboard_client_api.rs",
+)];
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    for (protocol, output) in PROTOCOLS {
+        println!("cargo:rerun-if-changed={}", protocol);
+        generate_code(protocol, Path::new(&out_dir).join(output), Side::Client);
+    }
+}
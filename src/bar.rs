@@ -4,254 +4,2602 @@ use smithay_client_toolkit::reexports::protocols::wlr::unstable::layer_shell::v1
     zwlr_layer_shell_v1, zwlr_layer_surface_v1,
 };
 
-use andrew::{shapes::rectangle, text, Canvas};
+use libwaylandsfpanel::{
+    CommandOutput, Font, Handle, Image, KeyCombo, Rect, Renderer, Timeout, Toplevel,
+};
+use log::{error, info, warn};
+use std::cell::{Cell, Ref, RefCell};
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
-use std::io::Read;
+use std::fs;
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Default hold duration of a long press
+const LONG_PRESS_MS: u64 = 500;
+
+/// Default timing of repeating buttons
+const REPEAT_DELAY_MS: u64 = 500;
+const REPEAT_INTERVAL_MS: u64 = 100;
+
+/// Default time between the runs of a label command
+const LABEL_INTERVAL_S: u64 = 10;
+
+/// Command ids, label commands use the index of their button
+const STATUS_COMMAND: u32 = u32::MAX;
+/// State commands use it plus the index of their button
+const STATE_COMMAND: u32 = 1 << 16;
+
+/// Timeout ids
+const LONG_PRESS_TIMEOUT: u32 = 0;
+const REPEAT_TIMEOUT: u32 = 1;
+/// Redraws the clocks
+const CLOCK_TIMEOUT: u32 = 2;
+/// Reads the batteries again
+const BATTERY_TIMEOUT: u32 = 3;
+/// Collapses an auto-hiding bar
+const COLLAPSE_TIMEOUT: u32 = 4;
+/// Moves a sliding bar
+const SLIDE_TIMEOUT: u32 = 5;
+
+/// Makes every copy of the bar redraw the toggles which have changed
+const TOGGLES_CHANGED: u32 = 0;
+/// Makes every copy of the bar redraw the workspaces if they have changed
+const WORKSPACES_CHANGED: u32 = 1;
+/// Makes every copy of the bar apply the requests to the control socket
+const CONTROL_REQUESTED: u32 = 2;
+/// Makes every copy of the bar switch to the latest configuration
+const CONFIG_RELOADED: u32 = 3;
+
+/// Time format of clocks which don't set one
+const CLOCK_FORMAT: &str = "%H:%M";
+
+/// Default distance of swipes along the bar, in bar heights
+const BAR_SWIPE_HEIGHTS: u32 = 3;
+
+/// Default time between battery reads
+const BATTERY_INTERVAL_S: u64 = 60;
+const CHARGING_TEXT: &str = "+";
+
+/// Defaults of auto-hiding bars
+const HANDLE_HEIGHT: u32 = 4;
+const COLLAPSE_MS: u64 = 3000;
+const SLIDE_MS: u64 = 150;
+/// Time between the steps of a slide, about a frame
+const SLIDE_STEP_MS: u64 = 16;
+
+struct Bar {
+    height: u32,
+    /// X, Y coordinates of current cursor position
+    pointer_location: Option<libwaylandsfpanel::PointerPosition>,
+    pointer_engaged: bool,
+    click_targets: Vec<ClickTarget>,
+    /// Actions of each configured button
+    actions: Vec<Option<ButtonActions>>,
+    /// The button a gesture has started on
+    pressed: Option<Press>,
+    /// Long press or repeat timeout of the pressed button
+    press_timeout: Option<Timeout>,
+    handle: Handle,
+    /// Icon of each configured button
+    icons: Vec<Option<Rc<Image>>>,
+    /// Style of each configured button
+    styles: Vec<Style>,
+    /// Latest styles, changed through the control socket
+    latest_styles: Rc<RefCell<Vec<Style>>>,
+    /// Output of the label command of each button
+    labels: Vec<Option<String>>,
+    /// Latest labels of every copy of the bar, for the copies created later
+    latest_labels: Rc<RefCell<Vec<Option<String>>>>,
+    /// Whether each toggle button is on, shared by every copy of the bar
+    toggle_states: Rc<RefCell<Vec<bool>>>,
+    /// States of the toggle buttons when they were drawn last
+    drawn_toggle_states: Vec<bool>,
+    /// Off and on look of each toggle button
+    toggle_looks: Vec<Option<[ToggleLook; 2]>>,
+    status: Status,
+    /// Latest status of every copy of the bar, for the copies created later
+    latest_status: Rc<RefCell<Status>>,
+    /// Input of the status command, if it's running
+    clicks: Option<Rc<RefCell<Clicks>>>,
+    /// Area taken by the status and by each of its blocks
+    status_area: Option<Rect>,
+    status_targets: Vec<Rect>,
+    cfg: Config,
+    bar_config: BarConfig,
+    /// The status uses it, buttons start from it
+    style: Style,
+    /// How often the clocks change, if there are any
+    clock_period: Option<Duration>,
+    clock_timeout: Option<Timeout>,
+    /// Sysfs directory and looks of each battery widget
+    batteries: Vec<Option<BatteryWidget>>,
+    /// State of each battery when it was read last
+    battery_states: Vec<Option<Battery>>,
+    battery_interval: Option<Duration>,
+    battery_timeout: Option<Timeout>,
+    /// Connection to sway, shared by every copy of the bar
+    sway: Option<Rc<RefCell<Sway>>>,
+    /// Workspaces when they were drawn last and the area of each
+    drawn_workspaces: Vec<sway::Workspace>,
+    workspace_targets: Vec<(Rect, usize)>,
+    /// Focused and urgent look of each workspace widget
+    workspace_looks: Vec<Option<[Style; 2]>>,
+    /// Windows when they were drawn last and the area of each
+    drawn_tasks: Vec<Toplevel>,
+    task_targets: Vec<(Rect, usize)>,
+    /// Look of the active window in each task widget
+    task_looks: Vec<Option<Style>>,
+    /// Actions of swipes along the bar, if there are any
+    bar_swipe: Option<BarSwipe>,
+    /// Where and when the current gesture has started
+    gesture_start: Option<libwaylandsfpanel::PointerPosition>,
+    gesture_time: Option<Instant>,
+    /// The current gesture has turned into a swipe along the bar
+    swiping: bool,
+    /// Moved below the output, requested through the control socket
+    hidden: bool,
+    latest_hidden: Rc<Cell<bool>>,
+    /// Buttons pressed through the control socket, which the first copy of
+    /// the bar to notice executes
+    pending_presses: Rc<RefCell<Vec<usize>>>,
+    /// The bar of the latest configuration, which copies are made from
+    latest: Rc<RefCell<Option<Bar>>>,
+    auto_hide: Option<AutoHide>,
+    /// Height of the part of an auto-hiding bar which shows
+    revealed: u32,
+    /// Whether an auto-hiding bar slides up or down
+    expanding: bool,
+    slide_timeout: Option<Timeout>,
+    collapse_timeout: Option<Timeout>,
+    /// The current gesture has expanded the bar, the buttons don't get it
+    revealing: bool,
+}
+
+mod battery;
+mod clock;
+mod config;
+mod control;
+mod control_socket;
+mod layout;
+mod status;
+mod sway;
+use battery::Battery;
+use config::{BarConfig, Button, ButtonKind, ColorConfig, ColorOverrides, Config, Group};
+use control::Request;
+use status::{Align, ClickEvent, Clicks, MinWidth, Status};
+use sway::Sway;
+
+impl Clone for Bar {
+    fn clone(&self) -> Self {
+        match &*self.latest.borrow() {
+            Some(latest) => latest.copy(),
+            None => self.copy(),
+        }
+    }
+}
+
+impl Bar {
+    /// A copy for another output, made from this bar even if there's a newer
+    /// one
+    fn copy(&self) -> Self {
+        Bar {
+            height: self.height,
+            pointer_engaged: false,
+            pointer_location: None,
+            click_targets: vec![],
+            actions: self.actions.clone(),
+            pressed: None,
+            press_timeout: None,
+            handle: self.handle.clone(),
+            icons: self.icons.clone(),
+            styles: self.latest_styles.borrow().clone(),
+            latest_styles: Rc::clone(&self.latest_styles),
+            labels: self.latest_labels.borrow().clone(),
+            latest_labels: Rc::clone(&self.latest_labels),
+            toggle_states: Rc::clone(&self.toggle_states),
+            drawn_toggle_states: self.toggle_states.borrow().clone(),
+            toggle_looks: self.toggle_looks.clone(),
+            status: self.latest_status.borrow().clone(),
+            latest_status: Rc::clone(&self.latest_status),
+            clicks: self.clicks.clone(),
+            status_area: None,
+            status_targets: vec![],
+            cfg: self.cfg.clone(),
+            bar_config: self.bar_config.clone(),
+            style: self.style.clone(),
+            clock_period: self.clock_period,
+            clock_timeout: None,
+            batteries: self.batteries.clone(),
+            battery_states: self.battery_states.clone(),
+            battery_interval: self.battery_interval,
+            battery_timeout: None,
+            sway: self.sway.clone(),
+            drawn_workspaces: vec![],
+            workspace_targets: vec![],
+            workspace_looks: self.workspace_looks.clone(),
+            drawn_tasks: vec![],
+            task_targets: vec![],
+            task_looks: self.task_looks.clone(),
+            bar_swipe: self.bar_swipe.clone(),
+            gesture_start: None,
+            gesture_time: None,
+            swiping: false,
+            hidden: self.latest_hidden.get(),
+            latest_hidden: Rc::clone(&self.latest_hidden),
+            pending_presses: Rc::clone(&self.pending_presses),
+            latest: Rc::clone(&self.latest),
+            auto_hide: self.auto_hide,
+            revealed: self
+                .auto_hide
+                .map_or(self.height, |auto_hide| auto_hide.handle_height),
+            expanding: false,
+            slide_timeout: None,
+            collapse_timeout: None,
+            revealing: false,
+        }
+    }
+
+    /// Index of the button at a position
+    fn button_at(&self, pos: libwaylandsfpanel::PointerPosition) -> Option<usize> {
+        self.click_targets
+            .iter()
+            .find_map(|click_target| click_target.process_click(pos))
+    }
+
+    /// Actions of a button, `None` for spacers
+    fn button_actions(&self, button: usize) -> Option<&ButtonActions> {
+        self.actions[button].as_ref()
+    }
+
+    fn press(&mut self, pos: libwaylandsfpanel::PointerPosition) {
+        self.release();
+
+        let button = match self.button_at(pos) {
+            Some(button) => button,
+            None => return,
+        };
+
+        let actions = match self.button_actions(button) {
+            Some(actions) => actions,
+            None => return,
+        };
+
+        if let Some(repeat) = &actions.repeat {
+            // fire right away, the release won't do anything
+            if let Some(tap) = &actions.tap {
+                self.execute(tap);
+            }
+            self.press_timeout = Some(self.handle.add_timeout(repeat.delay, REPEAT_TIMEOUT));
+        } else if actions.long_press.is_some()
+            || self.bar_config.buttons[button].kind == ButtonKind::Tasks
+        {
+            self.press_timeout = Some(
+                self.handle
+                    .add_timeout(actions.hold_duration, LONG_PRESS_TIMEOUT),
+            );
+        }
+
+        self.pressed = Some(Press {
+            button,
+            start: pos,
+            long: false,
+        });
+    }
+
+    fn release(&mut self) {
+        self.pressed = None;
+        self.cancel_press_timeout();
+    }
+
+    fn cancel_press_timeout(&mut self) {
+        if let Some(timeout) = self.press_timeout.take() {
+            self.handle.cancel_timeout(&timeout);
+        }
+    }
+
+    /// The pressed button, if the pointer is still on it
+    fn held_button(&self) -> Option<usize> {
+        let current = self.pointer_location.and_then(|pos| self.button_at(pos));
+
+        match self.pressed {
+            Some(press) if Some(press.button) == current => current,
+            _ => None,
+        }
+    }
+
+    fn check_execute_click(&mut self) {
+        let pressed = self.pressed;
+        self.release();
+
+        if self.swiping {
+            if let Some(direction) = self.bar_swipe_direction() {
+                self.execute_bar_swipe(direction);
+            }
+            return;
+        }
+
+        // gestures on the status don't start on a button
+        if pressed.is_none() && self.click_status() {
+            return;
+        }
+
+        // repeating buttons fire while being held
+        if let Some(press) = pressed {
+            if let Some(ButtonActions {
+                repeat: Some(_), ..
+            }) = self.button_actions(press.button)
+            {
+                return;
+            }
+        }
+
+        if let Some(press) = pressed {
+            if self.bar_config.buttons[press.button].kind == ButtonKind::Tasks {
+                self.task_gesture(press);
+                return;
+            }
+        }
+
+        // a swipe is bound to the button it started on
+        if let (Some(press), Some(end)) = (pressed, self.pointer_location) {
+            let swipe = swipe_direction(press.start, end, self.height as f64 / 2.)
+                .and_then(|direction| self.button_actions(press.button)?.swipe(direction));
+
+            if let Some(handler) = swipe {
+                self.execute(handler);
+                return;
+            }
+        }
+
+        let button = match self.pointer_location.and_then(|pos| self.button_at(pos)) {
+            Some(button) => button,
+            None => return,
+        };
+
+        if self.bar_config.buttons[button].kind == ButtonKind::Workspaces {
+            self.focus_workspace();
+            return;
+        }
+
+        let actions = match self.button_actions(button) {
+            Some(actions) => actions,
+            None => return,
+        };
+        let handler = match (pressed, &actions.long_press) {
+            (
+                Some(Press {
+                    button: pressed,
+                    long: true,
+                    ..
+                }),
+                Some(long_press),
+            ) if pressed == button => long_press,
+            _ => match &actions.tap {
+                Some(tap) => tap,
+                None => return,
+            },
+        };
+
+        self.execute(handler);
+    }
+
+    /// Width of each status block, without the gaps between them
+    fn status_block_widths(&self) -> Vec<u32> {
+        let style = &self.style;
+
+        self.status
+            .blocks
+            .iter()
+            .map(|block| {
+                let min_width = match &block.min_width {
+                    Some(MinWidth::Pixels(width)) => *width,
+                    Some(MinWidth::Text(text)) => style.font.text_width(text, style.font_size),
+                    None => 0,
+                };
+
+                cmp::max(
+                    style.font.text_width(&block.full_text, style.font_size),
+                    min_width,
+                )
+            })
+            .collect()
+    }
+
+    fn status_width(&self) -> u32 {
+        let gaps: u32 = self
+            .status
+            .blocks
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|block| block.gap())
+            .sum();
+
+        self.status_block_widths().iter().sum::<u32>() + gaps
+    }
+
+    fn draw_status(&mut self, renderer: &mut dyn Renderer, span: layout::Span, height: i32) {
+        let style = &self.style;
+        let separator_width = self.bar_config.separator_width.unwrap_or(0);
+        let widths = self.status_block_widths();
+        let blocks = &self.status.blocks;
+
+        self.status_area = Some(Rect::new(span.x, 0, span.width, height as u32));
+        self.status_targets.clear();
+
+        let text_y = ((height as f32 - style.font_size) / 2.) as i32;
+        let mut x = span.x;
+
+        for (index, (block, width)) in blocks.iter().zip(widths).enumerate() {
+            let rect = Rect::new(x, 0, width, height as u32);
+            if block.urgent {
+                renderer.fill_rect(rect, style.colors.urgent_color);
+            }
+
+            let text_width = style.font.text_width(&block.full_text, style.font_size) as i32;
+            let text_x = match block.align {
+                Align::Left => x,
+                Align::Center => x + (width as i32 - text_width) / 2,
+                Align::Right => rect.right() - text_width,
+            };
+            let color = block
+                .color
+                .as_deref()
+                .and_then(|color| config::parse_color("color", color).ok())
+                .unwrap_or(style.colors.text_color);
+
+            renderer.set_clip(Some(rect));
+            renderer.draw_text(
+                &block.full_text,
+                &style.font,
+                style.font_size,
+                (text_x, text_y),
+                color,
+            );
+            renderer.set_clip(None);
+
+            self.status_targets.push(rect);
+            x = rect.right();
+
+            if index + 1 < blocks.len() {
+                let gap = block.gap();
+
+                if block.has_separator() && separator_width > 0 {
+                    let line_x = x + (gap as i32 - separator_width as i32) / 2;
+                    let line = Rect::new(line_x, height / 4, separator_width, height as u32 / 2);
+                    renderer.fill_rect(line, style.colors.separator_color);
+                }
+
+                x += gap as i32;
+            }
+        }
+    }
+
+    /// Sends a click event to the status command if the gesture has started
+    /// on a status block, returns whether it has
+    fn click_status(&self) -> bool {
+        let (pos, end, time) = match (self.gesture_start, self.pointer_location, self.gesture_time)
+        {
+            (Some(pos), Some(end), Some(time)) => (pos, end, time),
+            _ => return false,
+        };
+
+        let (block, rect) = match self
+            .status
+            .blocks
+            .iter()
+            .zip(&self.status_targets)
+            .find(|(_, rect)| rect.contains(pos.0, pos.1))
+        {
+            Some(target) => target,
+            None => return false,
+        };
+
+        if let (true, Some(clicks)) = (self.status.click_events(), &self.clicks) {
+            let event = ClickEvent {
+                name: block.name.as_deref(),
+                instance: block.instance.as_deref(),
+                button: status_button(pos, end, time.elapsed(), self.height as f64 / 2.),
+                x: pos.0 as i32,
+                y: pos.1 as i32,
+                relative_x: pos.0 as i32 - rect.x,
+                relative_y: pos.1 as i32 - rect.y,
+                width: rect.width,
+                height: rect.height,
+            };
+
+            if let Err(e) = clicks.borrow_mut().send(&event) {
+                warn!("can't send a click to the status command: {}", e);
+            }
+        }
+
+        true
+    }
+
+    /// Redraws the toggle buttons whose state has changed since the last draw
+    fn toggle_changes(&self) -> Option<libwaylandsfpanel::RenderEvent> {
+        let states = self.toggle_states.borrow();
+
+        self.area_of(|button| states[button] != self.drawn_toggle_states[button])
+            .map(libwaylandsfpanel::RenderEvent::RenderArea)
+    }
+
+    /// Applies the requests to the control socket, see `Controller`
+    fn control_changes(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        let presses: Vec<usize> = self.pending_presses.borrow_mut().drain(..).collect();
+        for button in presses {
+            if let Some(ButtonActions { tap: Some(tap), .. }) = self.button_actions(button) {
+                self.execute(tap);
+            }
+        }
+
+        let labels = self.latest_labels.borrow().clone();
+        let styles = self.latest_styles.borrow().clone();
+        let changed: Vec<bool> = (0..labels.len())
+            .map(|i| labels[i] != self.labels[i] || styles[i].colors != self.styles[i].colors)
+            .collect();
+        self.labels = labels;
+        self.styles = styles;
+
+        let hidden = self.latest_hidden.get();
+        if hidden != self.hidden {
+            self.hidden = hidden;
+            return Some(libwaylandsfpanel::RenderEvent::Reconfigure);
+        }
+
+        // nothing to redraw if the bar hasn't been drawn yet
+        self.area_of(|button| changed[button])
+            .map(libwaylandsfpanel::RenderEvent::RenderArea)
+    }
+
+    /// Whether only the handle of an auto-hiding bar shows
+    fn collapsed(&self) -> bool {
+        match self.auto_hide {
+            Some(auto_hide) => !self.expanding && self.revealed == auto_hide.handle_height,
+            None => false,
+        }
+    }
+
+    /// Slides an auto-hiding bar up or down, unless it's on its way already
+    fn slide(&mut self, expanding: bool) -> Option<libwaylandsfpanel::RenderEvent> {
+        if self.auto_hide.is_none() || self.expanding == expanding {
+            return None;
+        }
+        self.expanding = expanding;
+
+        match self.slide_timeout {
+            Some(_) => None,
+            None => self.slide_step(),
+        }
+    }
+
+    /// Moves a sliding bar by a step, applying the settings again moves the
+    /// surface
+    fn slide_step(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        let auto_hide = self.auto_hide?;
+        self.slide_timeout = None;
+
+        let steps = cmp::max(1, auto_hide.slide.as_millis() as u32 / SLIDE_STEP_MS as u32);
+        let step = cmp::max(1, (self.height - auto_hide.handle_height) / steps);
+        let target = match self.expanding {
+            true => self.height,
+            false => auto_hide.handle_height,
+        };
+
+        self.revealed = match self.expanding {
+            true => cmp::min(self.revealed + step, target),
+            false => cmp::max(self.revealed.saturating_sub(step), target),
+        };
+        if self.revealed != target {
+            self.slide_timeout = Some(
+                self.handle
+                    .add_timeout(Duration::from_millis(SLIDE_STEP_MS), SLIDE_TIMEOUT),
+            );
+        }
+
+        Some(libwaylandsfpanel::RenderEvent::Reconfigure)
+    }
+
+    /// Collapses an auto-hiding bar once it hasn't been touched for a while
+    fn schedule_collapse(&mut self) {
+        let auto_hide = match self.auto_hide {
+            Some(auto_hide) => auto_hide,
+            None => return,
+        };
+
+        if let Some(timeout) = self.collapse_timeout.take() {
+            self.handle.cancel_timeout(&timeout);
+        }
+
+        self.collapse_timeout = Some(
+            self.handle
+                .add_timeout(auto_hide.collapse_after, COLLAPSE_TIMEOUT),
+        );
+    }
+
+    /// Width of each workspace button, at least as wide as the bar is high
+    fn workspace_widths(&self, style: &Style) -> Vec<u32> {
+        self.drawn_workspaces
+            .iter()
+            .map(|workspace| {
+                let text_width = style.font.text_width(&workspace.name, style.font_size);
+                cmp::max(self.height, text_width + self.height / 2)
+            })
+            .collect()
+    }
+
+    fn workspaces_width(&self, style: &Style) -> u32 {
+        self.workspace_widths(style).iter().sum()
+    }
+
+    /// Draws a widget made of a button for each item, returns the area of
+    /// each of them
+    fn draw_segments(
+        &self,
+        renderer: &mut dyn Renderer,
+        index: usize,
+        span: layout::Span,
+        height: i32,
+        segments: &[Segment],
+    ) -> Vec<Rect> {
+        let block = Rect::new(span.x, 0, span.width, height as u32);
+        renderer.fill_rect(block, self.styles[index].colors.button_color);
+
+        let held = match (self.held_button(), self.pointer_location) {
+            (Some(button), Some(pos)) if button == index => Some(pos),
+            _ => None,
+        };
+        let long_pressed = match self.pressed {
+            Some(Press {
+                button, long: true, ..
+            }) => button == index,
+            _ => false,
+        };
+
+        let mut x = span.x;
+        let mut rects = vec![];
+        for segment in segments {
+            let style = segment.style;
+            let rect = Rect::new(x, 0, segment.width, height as u32);
+            x = rect.right();
+
+            let held_here = held.is_some_and(|pos| rect.contains(pos.0, pos.1));
+            let color = match held_here {
+                true => style.colors.button_pressed_color,
+                false => style.colors.button_color,
+            };
+            renderer.fill_rect(rect, color);
+
+            if held_here && long_pressed {
+                let mark_height = cmp::max(height as u32 / 16, 2);
+                let mark = Rect::new(rect.x, 0, rect.width, mark_height);
+                renderer.fill_rect(mark, style.colors.text_color);
+            }
+
+            let text_width = style.font.text_width(segment.text, style.font_size) as i32;
+            let text_pos = (
+                rect.x + (rect.width as i32 - text_width) / 2,
+                ((height as f32 - style.font_size) / 2.) as i32,
+            );
+
+            renderer.set_clip(Some(rect));
+            renderer.draw_text(
+                segment.text,
+                &style.font,
+                style.font_size,
+                text_pos,
+                style.colors.text_color,
+            );
+            renderer.set_clip(None);
+
+            rects.push(rect);
+        }
+
+        rects
+    }
+
+    fn draw_workspaces(
+        &self,
+        renderer: &mut dyn Renderer,
+        index: usize,
+        span: layout::Span,
+        height: i32,
+    ) -> Vec<Rect> {
+        let style = &self.styles[index];
+        let mut widths = self.workspace_widths(style);
+
+        // a fixed width is shared evenly
+        if widths.iter().sum::<u32>() != span.width && !widths.is_empty() {
+            let width = span.width / widths.len() as u32;
+            widths = vec![width; widths.len()];
+        }
+
+        let looks = self.workspace_looks[index].as_ref();
+        let segments: Vec<Segment> = self
+            .drawn_workspaces
+            .iter()
+            .zip(widths)
+            .map(|(workspace, width)| Segment {
+                text: &workspace.name,
+                width,
+                style: match (workspace.focused, workspace.urgent, looks) {
+                    (true, _, Some([focused, _])) => focused,
+                    (false, true, Some([_, urgent])) => urgent,
+                    _ => style,
+                },
+            })
+            .collect();
+
+        self.draw_segments(renderer, index, span, height, &segments)
+    }
+
+    /// Switches to the workspace under the pointer
+    fn focus_workspace(&self) {
+        let (pos, sway) = match (self.pointer_location, &self.sway) {
+            (Some(pos), Some(sway)) => (pos, sway),
+            _ => return,
+        };
+
+        if let Some(item) = segment_at(&self.workspace_targets, pos) {
+            let workspace = &self.drawn_workspaces[item];
+            if let Err(e) = sway.borrow_mut().focus(&workspace.name) {
+                warn!("can't switch to workspace '{}': {}", workspace.name, e);
+            }
+        }
+    }
+
+    fn draw_tasks(
+        &self,
+        renderer: &mut dyn Renderer,
+        index: usize,
+        span: layout::Span,
+        height: i32,
+    ) -> Vec<Rect> {
+        let style = &self.styles[index];
+        let show_title = self.bar_config.buttons[index]
+            .tasks
+            .as_ref()
+            .is_some_and(|tasks| tasks.show_title);
+        let width = span.width / cmp::max(self.drawn_tasks.len() as u32, 1);
+
+        let segments: Vec<Segment> = self
+            .drawn_tasks
+            .iter()
+            .map(|task| Segment {
+                // not every window has both
+                text: match (show_title, task.title.is_empty(), task.app_id.is_empty()) {
+                    (true, false, _) | (false, false, true) => &task.title,
+                    _ => &task.app_id,
+                },
+                width,
+                style: match (task.activated, &self.task_looks[index]) {
+                    (true, Some(active)) => active,
+                    _ => style,
+                },
+            })
+            .collect();
+
+        self.draw_segments(renderer, index, span, height, &segments)
+    }
+
+    /// Activates the window a tap has started on, or closes it after a long
+    /// press or a swipe up
+    fn task_gesture(&self, press: Press) {
+        let item = match segment_at(&self.task_targets, press.start) {
+            Some(item) => item,
+            None => return,
+        };
+        let id = self.drawn_tasks[item].id;
+
+        let end = match self.pointer_location {
+            Some(end) => end,
+            None => return,
+        };
+
+        match swipe_direction(press.start, end, self.height as f64 / 2.) {
+            Some(Direction::Up) => self.handle.close_toplevel(id),
+            Some(_) => {}
+            None if segment_at(&self.task_targets, end) != Some(item) => {}
+            None if press.long => self.handle.close_toplevel(id),
+            None => self.handle.activate_toplevel(id),
+        }
+    }
+
+    /// Redraws the workspaces if they have changed since the last draw
+    fn workspace_changes(&self) -> Option<libwaylandsfpanel::RenderEvent> {
+        let sway = self.sway.as_ref()?.borrow();
+        if sway.workspaces == self.drawn_workspaces {
+            return None;
+        }
+
+        // other workspaces change the layout
+        let names = |workspaces: &[sway::Workspace]| -> Vec<String> {
+            workspaces
+                .iter()
+                .map(|workspace| workspace.name.clone())
+                .collect()
+        };
+        if names(&sway.workspaces) != names(&self.drawn_workspaces) {
+            return Some(libwaylandsfpanel::RenderEvent::Render);
+        }
+
+        self.area_of(|button| self.bar_config.buttons[button].kind == ButtonKind::Workspaces)
+            .map(libwaylandsfpanel::RenderEvent::RenderArea)
+    }
+
+    /// Wakes up this copy of the bar when the clocks change next
+    fn schedule_clocks(&mut self) {
+        if let Some(period) = self.clock_period {
+            self.clock_timeout = Some(
+                self.handle
+                    .add_timeout(clock::until_next(period), CLOCK_TIMEOUT),
+            );
+        }
+    }
+
+    /// Area taken by the clocks, as last drawn
+    fn clocks_area(&self) -> Option<Rect> {
+        self.area_of(|button| self.bar_config.buttons[button].kind == ButtonKind::Clock)
+    }
+
+    /// Area of the drawn buttons matching `pred`, `None` if there are none or
+    /// the bar hasn't been drawn yet
+    fn area_of(&self, pred: impl Fn(usize) -> bool) -> Option<Rect> {
+        self.click_targets
+            .iter()
+            .filter(|click_target| pred(click_target.button))
+            .map(ClickTarget::rect)
+            .reduce(|a, b| a.union(&b))
+    }
+
+    fn schedule_batteries(&mut self) {
+        if let Some(interval) = self.battery_interval {
+            self.battery_timeout = Some(self.handle.add_timeout(interval, BATTERY_TIMEOUT));
+        }
+    }
+
+    /// Reads every battery, returns the area of the ones which have changed
+    fn read_batteries(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        let mut changed = vec![false; self.batteries.len()];
+        for (index, widget) in self.batteries.iter().enumerate() {
+            if let Some(widget) = widget {
+                let state = widget.read();
+                changed[index] = state != self.battery_states[index];
+                self.battery_states[index] = state;
+            }
+        }
+
+        self.area_of(|button| changed[button])
+            .map(libwaylandsfpanel::RenderEvent::RenderArea)
+    }
+
+    /// Direction of the current gesture if it's a swipe along the bar
+    fn bar_swipe_direction(&self) -> Option<Direction> {
+        let (swipe, start, end) = match (&self.bar_swipe, self.gesture_start, self.pointer_location)
+        {
+            (Some(swipe), Some(start), Some(end)) => (swipe, start, end),
+            _ => return None,
+        };
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+
+        match dx.abs() >= swipe.threshold && dx.abs() > dy.abs() {
+            true if dx > 0. => Some(Direction::Right),
+            true => Some(Direction::Left),
+            false => None,
+        }
+    }
+
+    fn execute_bar_swipe(&self, direction: Direction) {
+        let swipe = match &self.bar_swipe {
+            Some(swipe) => swipe,
+            None => return,
+        };
+        let command = match direction {
+            Direction::Left => &swipe.left,
+            Direction::Right => &swipe.right,
+            _ => return,
+        };
+
+        match command {
+            Some(launch) => self.run(launch),
+            None if swipe.windows => self.switch_window(direction),
+            None => {}
+        }
+    }
+
+    /// Activates the window before the active one for a swipe to the right,
+    /// or the one after it for a swipe to the left
+    fn switch_window(&self, direction: Direction) {
+        let windows = self.handle.toplevels();
+        if windows.is_empty() {
+            return;
+        }
+
+        let active = windows.iter().position(|window| window.activated);
+        let index = match (active, direction) {
+            (Some(active), Direction::Right) => (active + windows.len() - 1) % windows.len(),
+            (Some(active), _) => (active + 1) % windows.len(),
+            (None, _) => 0,
+        };
+
+        self.handle.activate_toplevel(windows[index].id);
+    }
+
+    fn execute(&self, handler: &ClickHandler) {
+        match handler {
+            ClickHandler::RunCommand(launch) => self.run(launch),
+            ClickHandler::PressKeys(combos) => {
+                if let Err(e) = self.handle.press_keys(combos) {
+                    error!("can't press keys: {}", e);
+                }
+            }
+            ClickHandler::Toggle { button, on, off } => {
+                let state = !self.toggle_states.borrow()[*button];
+                self.toggle_states.borrow_mut()[*button] = state;
+
+                self.run(match state {
+                    true => on,
+                    false => off,
+                });
+
+                self.handle.notify(TOGGLES_CHANGED);
+            }
+        }
+    }
+
+    fn run(&self, launch: &Launch) {
+        if launch.single_instance && self.handle.is_running(&launch.name) {
+            info!("'{}' is still running", launch.name);
+            return;
+        }
+
+        info!("running '{}'", launch.name);
+
+        if let Err(e) = self
+            .handle
+            .spawn(&launch.name, &mut launch.command(), launch.log_output)
+        {
+            error!("can't run '{}': {}", launch.name, e);
+        }
+    }
+
+    /// Builds a bar from a configuration without starting any commands
+    fn load(handle: Handle, cfg: Config) -> Result<Bar, String> {
+        let bar_config = cfg.clone().bar.unwrap();
+
+        let colors = cfg.get_color_config()?.with_overrides(&bar_config.colors)?;
+
+        let actions = bar_config
+            .buttons
+            .iter()
+            .enumerate()
+            .map(|(index, button)| match button.spacer.unwrap_or(false) {
+                true => Ok(None),
+                false => button_actions(index, button).map(Some),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let style = Style {
+            colors,
+            font: load_font(&bar_config.font)?,
+            font_size: bar_config
+                .font_size
+                .unwrap_or(bar_config.height as f32 / 2.),
+        };
+
+        let styles = button_styles(&bar_config, &style)?;
+
+        let icon_theme = bar_config.icon_theme.as_deref().unwrap_or("hicolor");
+        let icons = bar_config
+            .buttons
+            .iter()
+            .zip(&styles)
+            .map(|(button, style)| {
+                load_button_icon(button, icon_theme, bar_config.height, &style.colors)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let labels = vec![None; bar_config.buttons.len()];
+
+        let toggle_looks = bar_config
+            .buttons
+            .iter()
+            .zip(&styles)
+            .map(|(button, style)| toggle_looks(button, style))
+            .collect::<Result<_, _>>()?;
+
+        let workspace_looks = bar_config
+            .buttons
+            .iter()
+            .zip(&styles)
+            .map(|(button, style)| workspace_looks(button, style))
+            .collect::<Result<_, _>>()?;
+
+        let task_looks = bar_config
+            .buttons
+            .iter()
+            .zip(&styles)
+            .map(|(button, style)| task_look(button, style))
+            .collect::<Result<_, _>>()?;
+
+        let toggle_states = vec![false; bar_config.buttons.len()];
+
+        let clock_period = bar_config
+            .buttons
+            .iter()
+            .filter(|button| button.kind == ButtonKind::Clock)
+            .map(|button| clock::period(button.format.as_deref().unwrap_or(CLOCK_FORMAT)))
+            .min();
+
+        let batteries = bar_config
+            .buttons
+            .iter()
+            .zip(&styles)
+            .map(|(button, style)| battery_widget(button, style))
+            .collect::<Result<Vec<_>, _>>()?;
+        let battery_states = batteries
+            .iter()
+            .map(|widget| widget.as_ref().and_then(BatteryWidget::read))
+            .collect();
+        let battery_interval = batteries
+            .iter()
+            .flatten()
+            .map(|widget| widget.interval)
+            .min();
+
+        let auto_hide = auto_hide(&bar_config);
+        let height = bar_config.height;
+
+        Ok(Bar {
+            height: bar_config.height,
+            pointer_engaged: false,
+            pointer_location: None,
+            click_targets: vec![],
+            actions,
+            pressed: None,
+            press_timeout: None,
+            handle,
+            icons,
+            latest_styles: Rc::new(RefCell::new(styles.clone())),
+            styles,
+            latest_labels: Rc::new(RefCell::new(labels.clone())),
+            labels,
+            toggle_states: Rc::new(RefCell::new(toggle_states.clone())),
+            drawn_toggle_states: toggle_states,
+            toggle_looks,
+            status: Status::default(),
+            latest_status: Rc::new(RefCell::new(Status::default())),
+            clicks: None,
+            status_area: None,
+            status_targets: vec![],
+            bar_swipe: bar_swipe(&bar_config),
+            cfg,
+            bar_config,
+            style,
+            clock_period,
+            clock_timeout: None,
+            batteries,
+            battery_states,
+            battery_interval,
+            battery_timeout: None,
+            sway: None,
+            drawn_workspaces: vec![],
+            workspace_targets: vec![],
+            workspace_looks,
+            drawn_tasks: vec![],
+            task_targets: vec![],
+            task_looks,
+            gesture_start: None,
+            gesture_time: None,
+            swiping: false,
+            hidden: false,
+            latest_hidden: Rc::new(Cell::new(false)),
+            pending_presses: Rc::new(RefCell::new(vec![])),
+            latest: Rc::new(RefCell::new(None)),
+            revealed: auto_hide.map_or(height, |auto_hide| auto_hide.handle_height),
+            auto_hide,
+            expanding: false,
+            slide_timeout: None,
+            collapse_timeout: None,
+            revealing: false,
+        })
+    }
+
+    /// Builds the bar of a new configuration, which keeps the connections of
+    /// this one and the state shared by its copies
+    fn reload(&self, cfg: Config) -> Result<Bar, String> {
+        let mut bar = Bar::load(self.handle.clone(), cfg)?;
+
+        if bar.bar_config.status_command != self.bar_config.status_command {
+            warn!("a new status_command only runs after a restart");
+        }
+        if bar.needs_toplevels() && !self.needs_toplevels() {
+            warn!("the windows are only tracked after a restart");
+        }
+
+        bar.clicks = self.clicks.clone();
+        bar.status = self.latest_status.borrow().clone();
+        bar.latest_status = Rc::clone(&self.latest_status);
+        bar.sway = match &self.sway {
+            Some(sway) => Some(Rc::clone(sway)),
+            None => connect_sway(&bar.handle, &bar.bar_config),
+        };
+        bar.hidden = self.latest_hidden.get();
+        bar.latest_hidden = Rc::clone(&self.latest_hidden);
+        bar.pending_presses = Rc::clone(&self.pending_presses);
+        bar.latest = Rc::clone(&self.latest);
+
+        Ok(bar)
+    }
+
+    /// Starts the commands of the buttons and whatever the widgets listen to
+    fn start(&self) {
+        let handle = &self.handle;
+
+        for (index, button) in self.bar_config.buttons.iter().enumerate() {
+            if let Some(command) = &button.label_command {
+                let launch = shell_launch(Some(button), command);
+                let name = launch.name.clone();
+                let interval = Duration::from_secs(button.interval.unwrap_or(LABEL_INTERVAL_S));
+
+                handle.run_periodically(index as u32, &name, interval, move || launch.command());
+            }
+        }
+
+        for (index, button) in self.bar_config.buttons.iter().enumerate() {
+            let toggle = match &button.toggle {
+                Some(toggle) => toggle,
+                None => continue,
+            };
+
+            if let Some(command) = &toggle.state_command {
+                let id = STATE_COMMAND + index as u32;
+                let launch = shell_launch(Some(button), command);
+                let name = launch.name.clone();
+
+                match toggle.interval {
+                    Some(interval) => handle.run_periodically(
+                        id,
+                        &name,
+                        Duration::from_secs(interval),
+                        move || launch.command(),
+                    ),
+                    None => {
+                        if let Err(e) = handle.run_for_output(id, &name, &mut launch.command()) {
+                            error!("can't run '{}': {}", name, e);
+                        }
+                    }
+                }
+            }
+        }
+
+        // batteries are read again right away when a power supply changes
+        if self.battery_interval.is_some() {
+            if let Err(e) = handle.watch_uevents() {
+                warn!("can't watch power supply events: {}", e);
+            }
+        }
+
+        if self.needs_toplevels() {
+            handle.watch_toplevels();
+        }
+    }
+
+    fn needs_toplevels(&self) -> bool {
+        self.task_looks.iter().any(Option::is_some)
+            || self.bar_swipe.as_ref().is_some_and(|swipe| swipe.windows)
+    }
+
+    /// Whether a newer configuration has been loaded, which this copy hasn't
+    /// switched to yet
+    fn outdated(&self) -> bool {
+        match &*self.latest.borrow() {
+            Some(latest) => !Rc::ptr_eq(&latest.latest_labels, &self.latest_labels),
+            None => false,
+        }
+    }
+
+    /// Switches to the bar of the latest configuration, see `reload_config`
+    fn reloaded(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        if !self.outdated() {
+            return None;
+        }
+
+        let bar = match &*self.latest.borrow() {
+            Some(latest) => latest.copy(),
+            None => return None,
+        };
+
+        let timeouts = [
+            self.press_timeout.take(),
+            self.clock_timeout.take(),
+            self.battery_timeout.take(),
+            self.slide_timeout.take(),
+            self.collapse_timeout.take(),
+        ];
+        for timeout in timeouts.iter().flatten() {
+            self.handle.cancel_timeout(timeout);
+        }
+
+        *self = bar;
+
+        // the height may have changed
+        Some(libwaylandsfpanel::RenderEvent::Reconfigure)
+    }
+}
+
+impl libwaylandsfpanel::Application for Bar {
+    fn new(handle: Handle) -> Self {
+        let config_file = match parse_args(env::args()) {
+            Ok(config_file) => config_file,
+            Err(message) => {
+                error!("{}", message);
+
+                process::exit(1);
+            }
+        };
+
+        clock::init();
+
+        let bar = parse_bar(&config_file).and_then(|cfg| Bar::load(handle.clone(), cfg));
+        let mut bar = match bar {
+            Ok(bar) => bar,
+            Err(message) => {
+                error!("{}", message);
+
+                process::exit(1);
+            }
+        };
+
+        bar.clicks = bar
+            .bar_config
+            .status_command
+            .as_ref()
+            .and_then(|status_command| {
+                let mut command = Command::new("/bin/sh");
+                command.arg("-c").arg(status_command);
+
+                match handle.spawn_reader(STATUS_COMMAND, status_command, &mut command) {
+                    Ok(stdin) => Some(Rc::new(RefCell::new(Clicks::new(stdin)))),
+                    Err(e) => {
+                        error!("can't run '{}': {}", status_command, e);
+                        None
+                    }
+                }
+            });
+        bar.sway = connect_sway(&handle, &bar.bar_config);
+
+        bar.start();
+        *bar.latest.borrow_mut() = Some(bar.copy());
+
+        listen_control(Controller {
+            handle: handle.clone(),
+            latest: Rc::clone(&bar.latest),
+            config_file: config_file.clone(),
+        });
+
+        // saving the configuration applies it right away
+        let latest = Rc::clone(&bar.latest);
+        let path = config_file.clone();
+        let result = handle.watch_file(Path::new(&config_file), move || {
+            if let Err(message) = reload_config(&latest, &path) {
+                error!("keeping the previous configuration: {}", message);
+            }
+        });
+        if let Err(e) = result {
+            warn!("can't watch '{}': {}", config_file, e);
+        }
+
+        bar
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.style.colors.is_opaque()
+    }
+
+    fn settings(&self) -> libwaylandsfpanel::ApplicationSettings {
+        // a hidden bar is moved below the bottom edge of the output, and so
+        // is the part of an auto-hiding bar which doesn't show
+        let (exclusive_zone, bottom) = match (self.hidden, self.auto_hide) {
+            (true, _) => (0, -(self.height as i32)),
+            (false, Some(auto_hide)) => (
+                auto_hide.exclusive_zone as i32,
+                self.revealed as i32 - self.height as i32,
+            ),
+            (false, None) => (self.height as i32, 0),
+        };
+
+        libwaylandsfpanel::ApplicationSettings {
+            namespace: String::from("ppkui_bar"),
+            layer: zwlr_layer_shell_v1::Layer::Overlay,
+            size: libwaylandsfpanel::WindowSize(0, self.height),
+            exclusive_zone,
+            margins: (0, 0, bottom, 0),
+            anchor: zwlr_layer_surface_v1::Anchor::Bottom
+                | zwlr_layer_surface_v1::Anchor::Left
+                | zwlr_layer_surface_v1::Anchor::Right,
+        }
+    }
+
+    fn draw(&mut self, size: libwaylandsfpanel::WindowSize, renderer: &mut dyn Renderer) {
+        let width = size.0 as i32;
+        let height = size.1 as i32;
+
+        renderer.clear(self.style.colors.background_color);
+        self.click_targets.clear();
+
+        // only the top of the surface shows, the buttons don't fit there
+        if self.collapsed() {
+            let handle_height = self.revealed;
+            renderer.fill_rect(
+                Rect::new(width / 2 - width / 16, 0, (width / 8) as u32, handle_height),
+                self.style.colors.separator_color,
+            );
+            return;
+        }
+
+        self.drawn_workspaces = match &self.sway {
+            Some(sway) => sway.borrow().workspaces.clone(),
+            None => vec![],
+        };
+        self.workspace_targets.clear();
+        self.drawn_tasks = self.handle.toplevels();
+        self.task_targets.clear();
+
+        let mut items: Vec<layout::Item> = self
+            .bar_config
+            .buttons
+            .iter()
+            .enumerate()
+            .map(|(index, button)| match (button.kind, button.width) {
+                (ButtonKind::Workspaces, None) => layout::Item {
+                    size: layout::Size::Fixed(self.workspaces_width(&self.styles[index])),
+                    ..layout_item(button)
+                },
+                _ => layout_item(button),
+            })
+            .collect();
+        if self.bar_config.status_command.is_some() {
+            items.push(layout::Item {
+                size: layout::Size::Fixed(self.status_width()),
+                group: self.bar_config.status_group.unwrap_or(Group::Right),
+                separated: true,
+            });
+        }
+
+        let options = layout::Options {
+            padding: self.bar_config.padding.unwrap_or(0),
+            spacing: self.bar_config.spacing.unwrap_or(0),
+            separator: self.bar_config.separator_width.unwrap_or(0),
+        };
+        let layout = layout::layout(&items, width as u32, &options);
+
+        for separator in &layout.separators {
+            let line = Rect::new(separator.x, height / 4, separator.width, height as u32 / 2);
+            renderer.fill_rect(line, self.style.colors.separator_color);
+        }
+
+        // Draw buttons
+        let create_button = |style: &Style,
+                             text: String,
+                             icon: Option<&Image>,
+                             index: usize,
+                             long_pressed: bool,
+                             held: bool,
+                             renderer: &mut dyn Renderer,
+                             pointer_engaged: bool,
+                             pointer: Option<libwaylandsfpanel::PointerPosition>,
+                             span: layout::Span| {
+            let colors = &style.colors;
+            let text_h = style.font_size;
+            let text_width = style.font.text_width(&text, text_h) as i32;
+            let button_width = span.width as usize;
+            let block_height = height as usize;
+            let block_pos = (span.x as usize, 0);
+
+            // the icon is placed to the left of the label, both centered together
+            let icon_size = height / 2;
+            let icon_width = match (icon, text.is_empty()) {
+                (None, _) => 0,
+                (Some(_), true) => icon_size,
+                (Some(_), false) => icon_size + icon_size / 4,
+            };
+            let content_x = span.x + (span.width as i32 - icon_width - text_width) / 2;
+            let text_pos = (
+                content_x + icon_width,
+                ((block_height as f32 - text_h) / 2.) as i32,
+            );
+            let size = (button_width, block_height);
+
+            // create a click target
+            let click_target = ClickTarget {
+                position: block_pos,
+                size,
+                button: index,
+            };
+
+            // a very ugly way to check if this click target is hovered
+            let hovered = {
+                let mut retval = false;
+                if let Some(click_position) = pointer {
+                    if click_target.process_click(click_position).is_some() {
+                        retval = true;
+                    }
+                };
+                retval && pointer_engaged
+            };
+
+            let color = match (held, hovered) {
+                (true, _) => colors.button_pressed_color,
+                (false, true) => colors.button_hover_color,
+                (false, false) => colors.button_color,
+            };
+
+            let block = Rect::new(block_pos.0 as i32, 0, size.0 as u32, size.1 as u32);
+            renderer.fill_rect(block, color);
+
+            // the long press action will be used once released
+            if long_pressed {
+                let mark_height = std::cmp::max(block_height as u32 / 16, 2);
+                let mark = Rect::new(block.x, 0, block.width, mark_height);
+                renderer.fill_rect(mark, colors.text_color);
+            }
+
+            // keep long labels inside of the button
+            renderer.set_clip(Some(block));
+            if let Some(icon) = icon {
+                let icon_rect = Rect::new(
+                    content_x,
+                    (height - icon_size) / 2,
+                    icon_size as u32,
+                    icon_size as u32,
+                );
+                renderer.draw_image(icon, icon_rect);
+            }
+            renderer.draw_text(&text, &style.font, text_h, text_pos, colors.text_color);
+            renderer.set_clip(None);
+
+            click_target
+        };
+
+        let held = self.held_button();
+        self.drawn_toggle_states = self.toggle_states.borrow().clone();
+
+        for (index, button) in self.bar_config.buttons.iter().enumerate() {
+            // workspaces and tasks are drawn by their own widgets below
+            if button.spacer.unwrap_or(false)
+                || button.kind == ButtonKind::Workspaces
+                || button.kind == ButtonKind::Tasks
+            {
+                continue;
+            }
+
+            let long_pressed = match self.pressed {
+                Some(Press {
+                    button, long: true, ..
+                }) => button == index,
+                _ => false,
+            };
+
+            let look = self.toggle_looks[index]
+                .as_ref()
+                .map(|looks| &looks[self.drawn_toggle_states[index] as usize]);
+            let battery = match (&self.batteries[index], self.battery_states[index]) {
+                (Some(widget), Some(state)) => Some((widget, state)),
+                _ => None,
+            };
+
+            let text = match (
+                &self.labels[index],
+                look.and_then(|look| look.text.as_ref()),
+            ) {
+                (Some(label), _) => label.clone(),
+                (None, Some(text)) => text.clone(),
+                (None, None) => match button.kind {
+                    ButtonKind::Button => button.text.clone(),
+                    ButtonKind::Workspaces | ButtonKind::Tasks => continue,
+                    ButtonKind::Clock => {
+                        clock::format(button.format.as_deref().unwrap_or(CLOCK_FORMAT))
+                    }
+                    ButtonKind::Battery => match battery {
+                        Some((widget, state)) => widget.text(state),
+                        None => button.text.clone(),
+                    },
+                },
+            };
+
+            let style = match (look, battery) {
+                (Some(look), _) => &look.style,
+                (None, Some((widget, state))) => widget.style(state).unwrap_or(&self.styles[index]),
+                (None, None) => &self.styles[index],
+            };
+
+            let click_target = create_button(
+                style,
+                text,
+                self.icons[index].as_deref(),
+                index,
+                long_pressed,
+                held == Some(index),
+                renderer,
+                self.pointer_engaged,
+                self.pointer_location,
+                layout.items[index],
+            );
+
+            self.click_targets.push(click_target);
+        }
+
+        for index in 0..self.bar_config.buttons.len() {
+            let span = layout.items[index];
+            match self.bar_config.buttons[index].kind {
+                ButtonKind::Workspaces => {
+                    let rects = self.draw_workspaces(renderer, index, span, height);
+                    self.workspace_targets.extend(rects.into_iter().zip(0..));
+                }
+                ButtonKind::Tasks => {
+                    let rects = self.draw_tasks(renderer, index, span, height);
+                    self.task_targets.extend(rects.into_iter().zip(0..));
+                }
+                _ => continue,
+            }
+
+            self.click_targets.push(ClickTarget {
+                position: (span.x as usize, 0),
+                size: (span.width as usize, height as usize),
+                button: index,
+            });
+        }
+
+        if let Some(span) = layout.items.get(self.bar_config.buttons.len()) {
+            self.draw_status(renderer, *span, height);
+        }
+
+        if self.clock_timeout.is_none() {
+            self.schedule_clocks();
+        }
+
+        if self.battery_timeout.is_none() {
+            self.schedule_batteries();
+        }
+    }
+
+    fn input_start_gesture(
+        &mut self,
+        pos: libwaylandsfpanel::PointerPosition,
+    ) -> Option<libwaylandsfpanel::RenderEvent> {
+        self.pointer_engaged = true;
+        self.pointer_location = Some(pos);
+        self.gesture_start = Some(pos);
+        self.gesture_time = Some(Instant::now());
+        self.swiping = false;
+
+        // a tap or a swipe up on the handle, or on the collapsing bar
+        if self.auto_hide.is_some() && !self.expanding {
+            self.revealing = true;
+            self.schedule_collapse();
+            return self.slide(true);
+        }
+        self.schedule_collapse();
+
+        self.press(pos);
+
+        Some(libwaylandsfpanel::RenderEvent::Render)
+    }
+
+    fn timeout(&mut self, id: u32) -> Option<libwaylandsfpanel::RenderEvent> {
+        match id {
+            SLIDE_TIMEOUT => return self.slide_step(),
+            COLLAPSE_TIMEOUT => {
+                self.collapse_timeout = None;
+                // not while a finger is still on the bar
+                if self.pointer_engaged {
+                    self.schedule_collapse();
+                    return None;
+                }
+                return self.slide(false);
+            }
+            BATTERY_TIMEOUT => {
+                self.battery_timeout = None;
+                self.schedule_batteries();
+                return self.read_batteries();
+            }
+            CLOCK_TIMEOUT => {
+                self.clock_timeout = None;
+                self.schedule_clocks();
+                return self
+                    .clocks_area()
+                    .map(libwaylandsfpanel::RenderEvent::RenderArea);
+            }
+            _ => {}
+        }
+
+        // a timeout cancelled too late still arrives
+        let button = match (self.press_timeout.take(), self.held_button()) {
+            (Some(_), Some(button)) => button,
+            _ => return None,
+        };
+
+        match id {
+            LONG_PRESS_TIMEOUT => {
+                if let Some(press) = &mut self.pressed {
+                    press.long = true;
+                }
+
+                Some(libwaylandsfpanel::RenderEvent::Render)
+            }
+            REPEAT_TIMEOUT => {
+                if let Some(actions) = self.button_actions(button) {
+                    if let (Some(repeat), Some(tap)) = (&actions.repeat, &actions.tap) {
+                        self.execute(tap);
+                        self.press_timeout =
+                            Some(self.handle.add_timeout(repeat.interval, REPEAT_TIMEOUT));
+                    }
+                }
+
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn notify(&mut self, id: u32) -> Option<libwaylandsfpanel::RenderEvent> {
+        match id {
+            TOGGLES_CHANGED => self.toggle_changes(),
+            WORKSPACES_CHANGED => self.workspace_changes(),
+            CONTROL_REQUESTED => self.control_changes(),
+            CONFIG_RELOADED => self.reloaded(),
+            _ => None,
+        }
+    }
+
+    fn command_output(&mut self, output: &CommandOutput) -> Option<libwaylandsfpanel::RenderEvent> {
+        // the commands of a new configuration can be faster than its
+        // notification, their ids only make sense to the new buttons
+        if self.outdated() {
+            let reconfigure = self.reloaded();
+            self.command_output(output);
+            return reconfigure;
+        }
+
+        if output.id >= STATE_COMMAND {
+            let button = (output.id - STATE_COMMAND) as usize;
+            self.toggle_states.borrow_mut()[button] = output.success;
+
+            return self.toggle_changes();
+        }
+
+        // label commands are identified by the index of their button
+        let button = output.id as usize;
+        let label = output.stdout.lines().next().unwrap_or_default().to_owned();
+
+        self.latest_labels.borrow_mut()[button] = Some(label.clone());
+        if self.labels[button].as_ref() == Some(&label) {
+            return None;
+        }
+        self.labels[button] = Some(label);
+
+        // nothing to redraw if the bar hasn't been drawn yet
+        self.click_targets
+            .iter()
+            .find(|click_target| click_target.button == button)
+            .map(|click_target| libwaylandsfpanel::RenderEvent::RenderArea(click_target.rect()))
+    }
+
+    fn command_line(&mut self, id: u32, line: &str) -> Option<libwaylandsfpanel::RenderEvent> {
+        if id != STATUS_COMMAND {
+            return None;
+        }
+
+        let width = self.status_width();
+        let changed = match self.status.update(line) {
+            Ok(changed) => changed,
+            Err(e) => {
+                warn!("status command: {}", e);
+                return None;
+            }
+        };
+
+        *self.latest_status.borrow_mut() = self.status.clone();
+        if !changed {
+            return None;
+        }
+
+        // the buttons only move if the width changes, and without an area
+        // nothing has been drawn yet, which the first configure will do
+        match self.status_area {
+            Some(area) if self.status_width() == width => {
+                Some(libwaylandsfpanel::RenderEvent::RenderArea(area))
+            }
+            Some(_) => Some(libwaylandsfpanel::RenderEvent::Render),
+            None => None,
+        }
+    }
+
+    fn toplevels_changed(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        if self.handle.toplevels() == self.drawn_tasks {
+            return None;
+        }
+
+        self.area_of(|button| self.bar_config.buttons[button].kind == ButtonKind::Tasks)
+            .map(libwaylandsfpanel::RenderEvent::RenderArea)
+    }
+
+    fn uevent(
+        &mut self,
+        event: &libwaylandsfpanel::Uevent,
+    ) -> Option<libwaylandsfpanel::RenderEvent> {
+        match event.subsystem.as_str() {
+            "power_supply" => self.read_batteries(),
+            _ => None,
+        }
+    }
+
+    fn input_stop_gesture(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        self.release();
+        self.swiping = false;
+        self.revealing = false;
+        self.pointer_engaged = false;
+        self.pointer_location = None; // TODO: maybe not
+        Some(libwaylandsfpanel::RenderEvent::Render)
+    }
+
+    fn input_movement(
+        &mut self,
+        pos: libwaylandsfpanel::PointerPosition,
+    ) -> Option<libwaylandsfpanel::RenderEvent> {
+        self.pointer_location = Some(pos);
+
+        if self.revealing {
+            return None;
+        }
+
+        // the buttons don't get swipes along the bar
+        if !self.swiping && self.bar_swipe_direction().is_some() {
+            self.swiping = true;
+            self.release();
+        }
+
+        // moving off a repeating button stops it for good
+        if self.press_timeout.is_some() && self.held_button().is_none() {
+            self.cancel_press_timeout();
+        }
+
+        Some(libwaylandsfpanel::RenderEvent::Render)
+    }
+
+    fn input_commit_gesture(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
+        self.pointer_engaged = false;
+        if self.revealing {
+            self.revealing = false;
+            return None;
+        }
+
+        let on_button = self.pressed.is_some() || self.swiping;
+        self.check_execute_click();
+
+        // an auto-hiding bar gets out of the way once it has been used
+        match on_button {
+            true => self.slide(false),
+            false => None,
+        }
+        .or(Some(libwaylandsfpanel::RenderEvent::Render))
+    }
+}
+
+/// Colors and font of a button
+#[derive(Clone)]
+struct Style {
+    colors: ColorConfig,
+    font: Font,
+    /// Text size in pixels
+    font_size: f32,
+}
+
+/// Look of a toggle button in one of its states
+#[derive(Clone)]
+struct ToggleLook {
+    text: Option<String>,
+    style: Style,
+}
+
+/// A power supply and how to show it
+#[derive(Clone)]
+struct BatteryWidget {
+    path: PathBuf,
+    interval: Duration,
+    charging_text: String,
+    /// Capacities below which the styles are used, the lowest first
+    levels: [(u32, Style); 2],
+}
+
+impl BatteryWidget {
+    fn read(&self) -> Option<Battery> {
+        battery::read(&self.path).ok()
+    }
+
+    fn text(&self, state: Battery) -> String {
+        match state.charging {
+            true => format!("{}%{}", state.capacity, self.charging_text),
+            false => format!("{}%", state.capacity),
+        }
+    }
+
+    /// Style of a low battery, if it isn't charging
+    fn style(&self, state: Battery) -> Option<&Style> {
+        if state.charging {
+            return None;
+        }
+
+        self.levels
+            .iter()
+            .find(|(below, _)| state.capacity < *below)
+            .map(|(_, style)| style)
+    }
+}
+
+#[derive(Clone)]
+enum ClickHandler {
+    /// Run command
+    RunCommand(Launch),
+    /// Emulate key presses
+    PressKeys(Vec<KeyCombo>),
+    /// Switch a toggle button and run the command for its new state
+    Toggle {
+        button: usize,
+        on: Launch,
+        off: Launch,
+    },
+}
+
+#[derive(Clone)]
+struct ButtonActions {
+    /// Clocks may not have one
+    tap: Option<ClickHandler>,
+    long_press: Option<ClickHandler>,
+    /// How long the button has to be held for `long_press`
+    hold_duration: Duration,
+    swipe_up: Option<ClickHandler>,
+    swipe_down: Option<ClickHandler>,
+    swipe_left: Option<ClickHandler>,
+    swipe_right: Option<ClickHandler>,
+    repeat: Option<Repeat>,
+}
+
+#[derive(Clone)]
+struct Repeat {
+    delay: Duration,
+    interval: Duration,
+}
+
+impl ButtonActions {
+    fn swipe(&self, direction: Direction) -> Option<&ClickHandler> {
+        match direction {
+            Direction::Up => self.swipe_up.as_ref(),
+            Direction::Down => self.swipe_down.as_ref(),
+            Direction::Left => self.swipe_left.as_ref(),
+            Direction::Right => self.swipe_right.as_ref(),
+        }
+    }
+}
 
-struct Bar {
-    height: u32,
-    /// X, Y coordinates of current cursor position
-    pointer_location: Option<libwaylandsfpanel::PointerPosition>,
-    pointer_engaged: bool,
-    click_targets: Vec<ClickTarget>,
-    font_data: Vec<u8>,
-    cfg: Config,
-    bar_config: BarConfig,
-    colors: ColorConfig,
+#[derive(Clone, Copy, Debug)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
-mod config;
-use config::{BarConfig, ColorConfig, Config};
+/// Mouse button of a gesture on the status like i3bar reports it: swipes
+/// scroll, a long press is the right button and a tap the left one
+fn status_button(
+    start: libwaylandsfpanel::PointerPosition,
+    end: libwaylandsfpanel::PointerPosition,
+    held: Duration,
+    min_distance: f64,
+) -> u32 {
+    match swipe_direction(start, end, min_distance) {
+        Some(Direction::Up) => 4,
+        Some(Direction::Down) => 5,
+        Some(Direction::Left) => 6,
+        Some(Direction::Right) => 7,
+        None if held >= Duration::from_millis(LONG_PRESS_MS) => 3,
+        None => 1,
+    }
+}
 
-impl Clone for Bar {
-    fn clone(&self) -> Self {
-        Bar {
-            height: self.height,
-            pointer_engaged: false,
-            pointer_location: None,
-            click_targets: vec![],
-            font_data: self.font_data.clone(),
-            cfg: self.cfg.clone(),
-            bar_config: self.bar_config.clone(),
-            colors: self.colors.clone(),
+/// Direction of a gesture which has moved at least `min_distance` away
+/// from where it started, along the axis it has moved on the most
+fn swipe_direction(
+    start: libwaylandsfpanel::PointerPosition,
+    end: libwaylandsfpanel::PointerPosition,
+    min_distance: f64,
+) -> Option<Direction> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+
+    if dx.hypot(dy) < min_distance {
+        return None;
+    }
+
+    Some(match dx.abs() > dy.abs() {
+        true if dx > 0. => Direction::Right,
+        true => Direction::Left,
+        false if dy > 0. => Direction::Down,
+        false => Direction::Up,
+    })
+}
+
+/// Timing of a bar which collapses to a handle
+#[derive(Clone, Copy)]
+struct AutoHide {
+    handle_height: u32,
+    exclusive_zone: u32,
+    collapse_after: Duration,
+    slide: Duration,
+}
+
+fn auto_hide(bar_config: &BarConfig) -> Option<AutoHide> {
+    let auto_hide = bar_config.auto_hide.as_ref()?;
+
+    Some(AutoHide {
+        handle_height: cmp::min(
+            auto_hide.handle_height.unwrap_or(HANDLE_HEIGHT),
+            bar_config.height,
+        ),
+        exclusive_zone: auto_hide.exclusive_zone.unwrap_or(0),
+        collapse_after: Duration::from_millis(auto_hide.timeout_ms.unwrap_or(COLLAPSE_MS)),
+        slide: Duration::from_millis(auto_hide.slide_ms.unwrap_or(SLIDE_MS)),
+    })
+}
+
+/// Actions of horizontal swipes along the whole bar
+#[derive(Clone)]
+struct BarSwipe {
+    left: Option<Launch>,
+    right: Option<Launch>,
+    /// Switch between the windows if there is no command
+    windows: bool,
+    /// Distance in pixels
+    threshold: f64,
+}
+
+fn bar_swipe(bar_config: &BarConfig) -> Option<BarSwipe> {
+    let launch =
+        |command: &Option<String>| command.as_ref().map(|command| shell_launch(None, command));
+
+    let swipe = BarSwipe {
+        left: launch(&bar_config.swipe_left_command),
+        right: launch(&bar_config.swipe_right_command),
+        windows: bar_config.swipe_windows,
+        threshold: bar_config
+            .swipe_threshold
+            .unwrap_or(bar_config.height * BAR_SWIPE_HEIGHTS) as f64,
+    };
+
+    match swipe.left.is_some() || swipe.right.is_some() || swipe.windows {
+        true => Some(swipe),
+        false => None,
+    }
+}
+
+/// An item of a widget made of several buttons
+struct Segment<'a> {
+    text: &'a str,
+    width: u32,
+    style: &'a Style,
+}
+
+/// The item of a widget at a position
+fn segment_at(targets: &[(Rect, usize)], pos: libwaylandsfpanel::PointerPosition) -> Option<usize> {
+    targets
+        .iter()
+        .find(|(rect, _)| rect.contains(pos.0, pos.1))
+        .map(|(_, item)| *item)
+}
+
+#[derive(Clone, Copy)]
+struct Press {
+    /// Index of the button
+    button: usize,
+    start: libwaylandsfpanel::PointerPosition,
+    /// Held for long enough to use the long press action
+    long: bool,
+}
+
+#[derive(Clone)]
+struct Launch {
+    /// Shown in the log and used to detect running instances
+    name: String,
+    /// Never empty
+    argv: Vec<String>,
+    env: HashMap<String, String>,
+    cwd: Option<String>,
+    single_instance: bool,
+    log_output: bool,
+}
+
+impl Launch {
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.argv[0]);
+        command.args(&self.argv[1..]).envs(&self.env);
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
         }
+
+        command
     }
 }
 
-impl Bar {
-    fn check_execute_click(&mut self) {
-        let mut matching_click_handler = None;
-        for click_target in &self.click_targets {
-            if let Some(click_position) = self.pointer_location {
-                if let Some(handler) = click_target.process_click(click_position) {
-                    matching_click_handler = Some(handler);
-                }
+fn layout_item(button: &Button) -> layout::Item {
+    let spacer = button.spacer.unwrap_or(false);
+
+    layout::Item {
+        size: match button.width {
+            Some(width) => layout::Size::Fixed(width),
+            None => layout::Size::Flex(button.weight.unwrap_or(1)),
+        },
+        group: button.group.unwrap_or(Group::Left),
+        separated: !spacer,
+    }
+}
+
+/// Applies the overrides of each button on top of the bar style, fonts
+/// used by several buttons are only loaded once
+fn button_styles(bar_config: &BarConfig, bar_style: &Style) -> Result<Vec<Style>, String> {
+    let mut fonts: HashMap<&str, Font> = HashMap::new();
+    fonts.insert(&bar_config.font, bar_style.font.clone());
+
+    let mut styles = vec![];
+    for button in &bar_config.buttons {
+        let path = button.font.as_deref().unwrap_or(&bar_config.font);
+        let font = match fonts.get(path) {
+            Some(font) => font.clone(),
+            None => {
+                let font = load_font(path)?;
+                fonts.insert(path, font.clone());
+                font
             }
+        };
+
+        styles.push(Style {
+            colors: bar_style.colors.with_overrides(&button.colors)?,
+            font,
+            font_size: button.font_size.unwrap_or(bar_style.font_size),
+        });
+    }
+
+    Ok(styles)
+}
+
+/// Off and on look of a toggle button
+fn toggle_looks(button: &Button, style: &Style) -> Result<Option<[ToggleLook; 2]>, String> {
+    let toggle = match &button.toggle {
+        Some(toggle) => toggle,
+        None => return Ok(None),
+    };
+
+    let look = |toggle_style: &config::ToggleStyle| -> Result<ToggleLook, String> {
+        Ok(ToggleLook {
+            text: toggle_style.text.clone(),
+            style: Style {
+                colors: style.colors.with_overrides(&toggle_style.colors)?,
+                ..style.clone()
+            },
+        })
+    };
+
+    Ok(Some([look(&toggle.off)?, look(&toggle.on)?]))
+}
+
+/// Finds the power supply of a battery widget, a missing one is only logged
+fn battery_widget(button: &Button, style: &Style) -> Result<Option<BatteryWidget>, String> {
+    if button.kind != ButtonKind::Battery {
+        return Ok(None);
+    }
+
+    let battery = button.battery.clone().unwrap_or_default();
+    let dir = battery
+        .power_supply_dir
+        .as_deref()
+        .unwrap_or(battery::POWER_SUPPLY_DIR);
+
+    let path = match battery::find(Path::new(dir), battery.supply.as_deref()) {
+        Ok(path) => path,
+        Err(message) => {
+            warn!("{}", message);
+            return Ok(None);
         }
+    };
 
-        match matching_click_handler {
-            Some(ClickHandler::RunCommand(cmd)) => {
-                match Command::new("/usr/bin/setsid")
-                    .arg("--fork")
-                    .arg("/bin/sh")
-                    .arg("-c")
-                    .arg(cmd)
-                    .spawn()
-                {
-                    Ok(mut child) => match child.wait() {
-                        Ok(..) => (),
-                        Err(e) => eprintln!("{:?}", e),
-                    },
-                    Err(e) => eprintln!("{:?}", e),
-                }
+    let level = |level: &config::BatteryLevel| -> Result<(u32, Style), String> {
+        let style = Style {
+            colors: style.colors.with_overrides(&level.colors)?,
+            ..style.clone()
+        };
+        Ok((level.below, style))
+    };
+
+    Ok(Some(BatteryWidget {
+        path,
+        interval: Duration::from_secs(battery.interval.unwrap_or(BATTERY_INTERVAL_S)),
+        charging_text: battery
+            .charging_text
+            .unwrap_or_else(|| CHARGING_TEXT.to_owned()),
+        levels: [level(&battery.critical)?, level(&battery.low)?],
+    }))
+}
+
+/// Focused and urgent look of a workspace widget
+fn workspace_looks(button: &Button, style: &Style) -> Result<Option<[Style; 2]>, String> {
+    if button.kind != ButtonKind::Workspaces {
+        return Ok(None);
+    }
+
+    let workspaces = button.workspaces.clone().unwrap_or_default();
+    let look = |colors: &config::ColorOverrides| -> Result<Style, String> {
+        Ok(Style {
+            colors: style.colors.with_overrides(colors)?,
+            ..style.clone()
+        })
+    };
+
+    Ok(Some([
+        look(&workspaces.focused)?,
+        look(&workspaces.urgent)?,
+    ]))
+}
+
+/// Look of the active window of a task widget
+fn task_look(button: &Button, style: &Style) -> Result<Option<Style>, String> {
+    if button.kind != ButtonKind::Tasks {
+        return Ok(None);
+    }
+
+    let tasks = button.tasks.clone().unwrap_or_default();
+    Ok(Some(Style {
+        colors: style.colors.with_overrides(&tasks.active)?,
+        ..style.clone()
+    }))
+}
+
+/// Connects to sway if there's a workspace widget, failures are only logged
+fn connect_sway(handle: &Handle, bar_config: &BarConfig) -> Option<Rc<RefCell<Sway>>> {
+    let button = bar_config
+        .buttons
+        .iter()
+        .find(|button| button.kind == ButtonKind::Workspaces)?;
+    let socket = button
+        .workspaces
+        .as_ref()
+        .and_then(|workspaces| workspaces.socket.clone())
+        .or_else(|| env::var("SWAYSOCK").ok());
+    let socket = match socket {
+        Some(socket) => socket,
+        None => {
+            warn!("SWAYSOCK is not set, can't show the workspaces");
+            return None;
+        }
+    };
+
+    let (sway, reader) = match Sway::connect(&socket) {
+        Ok(connection) => connection,
+        Err(e) => {
+            warn!("can't connect to sway at '{}': {}", socket, e);
+            return None;
+        }
+    };
+    let sway = Rc::new(RefCell::new(sway));
+
+    let sway_handle = Rc::clone(&sway);
+    let notifier = handle.clone();
+    let result = handle.read_socket("sway", reader, move |data| {
+        let changed = match data.is_empty() {
+            true => {
+                warn!("the connection to sway has been closed");
+                sway_handle.borrow_mut().workspaces.clear();
+                true
             }
-            None => {}
+            false => sway_handle.borrow_mut().receive(data),
+        };
+
+        if changed {
+            notifier.notify(WORKSPACES_CHANGED);
+        }
+    });
+
+    match result {
+        Ok(()) => Some(sway),
+        Err(e) => {
+            warn!("can't read from sway: {}", e);
+            None
         }
     }
 }
 
-impl libwaylandsfpanel::Application for Bar {
-    fn new() -> Self {
-        let cfg = match parse_bar(env::args()) {
-            Ok(args) => args,
-            Err(message) => {
-                eprintln!("{}", message);
+/// Answers the requests to the control socket, changing the state shared by
+/// every copy of the bar
+struct Controller {
+    handle: Handle,
+    latest: Rc<RefCell<Option<Bar>>>,
+    config_file: String,
+}
 
-                process::exit(1);
+impl Controller {
+    fn bar(&self) -> Ref<'_, Bar> {
+        // set by `Application::new`
+        Ref::map(self.latest.borrow(), |latest| latest.as_ref().unwrap())
+    }
+
+    /// Finds a button by its name, its index or its text
+    fn find_button(&self, id: &str) -> Result<usize, String> {
+        let bar = self.bar();
+        let buttons = &bar.bar_config.buttons;
+
+        let by_name = buttons
+            .iter()
+            .position(|button| button.name.as_deref() == Some(id));
+        let by_index = id.parse().ok().filter(|index| *index < buttons.len());
+        let by_text = || buttons.iter().position(|button| button.text == id);
+
+        by_name
+            .or(by_index)
+            .or_else(by_text)
+            .ok_or_else(|| format!("no button '{}'", id))
+    }
+
+    /// Returns the reply to a request
+    fn handle(&self, request: &Request) -> Result<String, String> {
+        match request {
+            Request::SetLabel { button, text } => {
+                let button = self.find_button(button)?;
+                self.bar().latest_labels.borrow_mut()[button] = Some(text.clone());
             }
-        };
+            Request::SetColor {
+                button,
+                name,
+                color,
+            } => {
+                let button = self.find_button(button)?;
+                let color = Some(color.clone());
+                let overrides = match name.as_str() {
+                    "button_color" => ColorOverrides {
+                        button_color: color,
+                        ..ColorOverrides::default()
+                    },
+                    "button_hover_color" => ColorOverrides {
+                        button_hover_color: color,
+                        ..ColorOverrides::default()
+                    },
+                    "button_pressed_color" => ColorOverrides {
+                        button_pressed_color: color,
+                        ..ColorOverrides::default()
+                    },
+                    "text_color" => ColorOverrides {
+                        text_color: color,
+                        ..ColorOverrides::default()
+                    },
+                    _ => return Err(format!("no button color '{}'", name)),
+                };
+
+                let bar = self.bar();
+                let mut styles = bar.latest_styles.borrow_mut();
+                styles[button].colors = styles[button].colors.with_overrides(&overrides)?;
+            }
+            Request::Show => self.bar().latest_hidden.set(false),
+            Request::Hide => self.bar().latest_hidden.set(true),
+            Request::PressButton { button } => {
+                let button = self.find_button(button)?;
+                let bar = self.bar();
+                match bar.button_actions(button) {
+                    Some(ButtonActions { tap: Some(_), .. }) => {
+                        bar.pending_presses.borrow_mut().push(button)
+                    }
+                    _ => return Err(format!("button '{}' has no tap action", button)),
+                }
+            }
+            Request::ReloadConfig => {
+                reload_config(&self.latest, &self.config_file)?;
+                return Ok(String::new());
+            }
+            Request::ListButtons => {
+                let list = self
+                    .bar()
+                    .bar_config
+                    .buttons
+                    .iter()
+                    .enumerate()
+                    .map(|(index, button)| {
+                        let name = button.name.as_deref().unwrap_or("-");
+                        format!("{}\t{}\t{:?}\t{}\n", index, name, button.kind, button.text)
+                    })
+                    .collect();
+                return Ok(list);
+            }
+        }
 
-        let colors = cfg.get_color_config();
+        self.handle.notify(CONTROL_REQUESTED);
 
-        let bar_config = cfg.clone().bar.unwrap();
+        Ok(String::new())
+    }
 
-        let mut font_data = Vec::new();
-        std::fs::File::open(bar_config.font.clone())
-            .unwrap()
-            .read_to_end(&mut font_data)
-            .unwrap();
+    /// Answers a request and closes the connection
+    fn respond(&self, line: &str, mut stream: UnixStream) {
+        let reply = line
+            .parse::<Request>()
+            .and_then(|request| self.handle(&request));
+        let reply = match &reply {
+            Ok(reply) => format!("{}ok\n", reply),
+            Err(message) => format!("error: {}\n", message),
+        };
 
-        Bar {
-            height: bar_config.height,
-            pointer_engaged: false,
-            pointer_location: None,
-            click_targets: vec![],
-            font_data,
-            cfg,
-            bar_config,
-            colors,
+        // the reader of the connection ends once the client has closed it
+        if let Err(e) = stream
+            .write_all(reply.as_bytes())
+            .and_then(|_| stream.shutdown(Shutdown::Both))
+        {
+            warn!("can't answer a control request: {}", e);
         }
     }
+}
 
-    fn settings(&self) -> libwaylandsfpanel::ApplicationSettings {
-        libwaylandsfpanel::ApplicationSettings {
-            namespace: String::from("ppkui_bar"),
-            layer: zwlr_layer_shell_v1::Layer::Overlay,
-            size: libwaylandsfpanel::WindowSize(0, self.height),
-            exclusive_zone: self.height as i32,
-            margins: (0, 0, 0, 0),
-            anchor: zwlr_layer_surface_v1::Anchor::Bottom
-                | zwlr_layer_surface_v1::Anchor::Left
-                | zwlr_layer_surface_v1::Anchor::Right,
+/// Listens on the control socket, failures are only logged
+fn listen_control(controller: Controller) {
+    let path = match control_socket::path() {
+        Some(path) => path,
+        None => {
+            warn!("XDG_RUNTIME_DIR is not set, not listening for control requests");
+            return;
+        }
+    };
+
+    // a socket file may be left behind by a bar which hasn't exited cleanly
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            warn!("another bar listens on '{}' already", path.display());
+            return;
         }
+        let _ = fs::remove_file(&path);
     }
 
-    fn draw(&mut self, size: libwaylandsfpanel::WindowSize, buf: &mut [u8]) {
-        let width = size.0 as i32;
-        let height = size.1 as i32;
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("can't listen on '{}': {}", path.display(), e);
+            return;
+        }
+    };
+
+    let handle = controller.handle.clone();
+    let controller = Rc::new(controller);
+    let reader_handle = handle.clone();
+    let result = handle.accept_connections("control socket", listener, move |stream| {
+        let reader = match stream.try_clone() {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("can't read a control request: {}", e);
+                return;
+            }
+        };
 
-        let text_h = height as f32 / 2.;
+        let controller = Rc::clone(&controller);
+        let mut request = vec![];
+        let mut stream = Some(stream);
+        let result = reader_handle.read_socket("control request", reader, move |data| {
+            request.extend_from_slice(data);
 
-        let mut canvas = andrew::Canvas::new(
-            buf,
-            width as usize,
-            height as usize,
-            4 * width as usize,
-            andrew::Endian::native(),
-        );
+            // requests are a single line, the end of the data ends them too
+            let end = match request.iter().position(|b| *b == b'\n') {
+                Some(end) => end,
+                None if data.is_empty() => request.len(),
+                None => return,
+            };
+            if let Some(stream) = stream.take() {
+                controller.respond(&String::from_utf8_lossy(&request[..end]), stream);
+            }
+        });
+        if let Err(e) = result {
+            warn!("can't read a control request: {}", e);
+        }
+    });
 
-        // Draw buttons
-        let mut next_draw_at = 0;
-        let per_button = (width as usize) / self.bar_config.buttons.len();
-
-        let mut create_button =
-            move |colors: &ColorConfig,
-                  text: String,
-                  action: String,
-                  font_data: &[u8],
-                  canvas: &mut Canvas,
-                  pointer_engaged: bool,
-                  pointer: Option<libwaylandsfpanel::PointerPosition>| {
-                let mut text =
-                    text::Text::new((0, 0), colors.text_color, font_data, text_h, 1.0, text);
-                let text_width = text.get_width();
-                let button_width = per_button;
-                let block_height = height as usize;
-                let block_pos = (next_draw_at, 0);
-                let text_pos = (
-                    block_pos.0 + (per_button - text_width) / 2,
-                    ((block_height as f32 - text_h) / 2.) as usize,
-                );
-                text.pos = text_pos;
-                let size = (button_width as usize, block_height as usize);
-
-                // create a click target
-                let click_target = ClickTarget {
-                    position: block_pos,
-                    size,
-                    handler: ClickHandler::RunCommand(action),
-                };
+    if let Err(e) = result {
+        warn!("can't listen on '{}': {}", path.display(), e);
+    }
+}
 
-                // a very ugly way to check if this click target is hovered
-                let hovered = {
-                    let mut retval = false;
-                    if let Some(click_position) = pointer {
-                        if let Some(..) = click_target.process_click(click_position) {
-                            retval = true;
-                        }
-                    };
-                    retval && pointer_engaged
-                };
+/// Builds the bar again from the configuration file and makes every copy of
+/// the bar switch to it, nothing changes if the configuration is invalid
+fn reload_config(latest: &Rc<RefCell<Option<Bar>>>, config_file: &str) -> Result<(), String> {
+    let bar = match &*latest.borrow() {
+        Some(current) => current.reload(parse_bar(config_file)?)?,
+        None => return Ok(()),
+    };
 
-                // TODO make colors configurable
-                let color = match hovered {
-                    false => colors.button_color,
-                    true => colors.button_hover_color,
-                };
+    info!("reloading '{}'", config_file);
 
-                let block = rectangle::Rectangle::new(block_pos, size, None, Some(color));
-                canvas.draw(&block);
-                canvas.draw(&text);
+    // the commands of the old buttons must not touch the new ones
+    bar.handle.forget_commands();
+    bar.start();
+    bar.handle.notify(CONFIG_RELOADED);
+    *latest.borrow_mut() = Some(bar);
 
-                next_draw_at += per_button;
+    Ok(())
+}
 
-                click_target
-            };
+fn load_font(path: &str) -> Result<Font, String> {
+    let data = std::fs::read(path).map_err(|e| format!("can't read font '{}': {}", path, e))?;
 
-        for button in self.bar_config.buttons.iter().cloned() {
-            let click_target = create_button(
-                &self.colors,
-                button.text,
-                button.command,
-                &self.font_data,
-                &mut canvas,
-                self.pointer_engaged,
-                self.pointer_location,
-            );
+    Font::from_data(data).ok_or_else(|| format!("can't parse font '{}'", path))
+}
 
-            self.click_targets.push(click_target);
+fn load_button_icon(
+    button: &Button,
+    theme: &str,
+    size: u32,
+    colors: &ColorConfig,
+) -> Result<Option<Rc<Image>>, String> {
+    let icon = match &button.icon {
+        Some(icon) => libwaylandsfpanel::icon::load_icon(icon, theme, size)?,
+        None => return Ok(None),
+    };
+
+    let icon = match button.icon_tint.unwrap_or(true) {
+        true => icon.tinted(colors.text_color),
+        false => icon,
+    };
+
+    Ok(Some(Rc::new(icon)))
+}
+
+fn button_actions(index: usize, button: &Button) -> Result<ButtonActions, String> {
+    let command = |command: &Option<String>| {
+        command
+            .as_ref()
+            .map(|command| ClickHandler::RunCommand(shell_launch(Some(button), command)))
+    };
+
+    if button.repeat.is_some() && button.long_press_command.is_some() {
+        return Err(format!(
+            "button '{}' can't both repeat and have a long press command",
+            button.text
+        ));
+    }
+
+    // a zero interval would fire on every pass of the event loop
+    if let Some(repeat) = &button.repeat {
+        if repeat.delay_ms == Some(0) || repeat.interval_ms == Some(0) {
+            return Err(format!(
+                "button '{}' needs a repeat delay_ms and interval_ms above 0",
+                button.text
+            ));
         }
     }
 
-    fn input_start_gesture(
-        &mut self,
-        pos: libwaylandsfpanel::PointerPosition,
-    ) -> Option<libwaylandsfpanel::RenderEvent> {
-        self.pointer_engaged = true;
-        self.pointer_location = Some(pos);
+    Ok(ButtonActions {
+        tap: tap_action(index, button)?,
+        long_press: command(&button.long_press_command),
+        hold_duration: Duration::from_millis(button.long_press_ms.unwrap_or(LONG_PRESS_MS)),
+        swipe_up: command(&button.swipe_up_command),
+        swipe_down: command(&button.swipe_down_command),
+        swipe_left: command(&button.swipe_left_command),
+        swipe_right: command(&button.swipe_right_command),
+        repeat: button.repeat.as_ref().map(|repeat| Repeat {
+            delay: Duration::from_millis(repeat.delay_ms.unwrap_or(REPEAT_DELAY_MS)),
+            interval: Duration::from_millis(repeat.interval_ms.unwrap_or(REPEAT_INTERVAL_MS)),
+        }),
+    })
+}
 
-        Some(libwaylandsfpanel::RenderEvent::Render)
+/// Runs a shell command with the environment options of a button, or the
+/// defaults for commands of the whole bar
+fn shell_launch(button: Option<&Button>, command: &str) -> Launch {
+    let argv = vec!["/bin/sh".into(), "-c".into(), command.to_owned()];
+    launch(button, command.to_owned(), argv)
+}
+
+fn launch(button: Option<&Button>, name: String, argv: Vec<String>) -> Launch {
+    Launch {
+        name,
+        argv,
+        env: button.and_then(|b| b.env.clone()).unwrap_or_default(),
+        cwd: button.and_then(|b| b.cwd.clone()),
+        single_instance: button.and_then(|b| b.single_instance).unwrap_or(false),
+        log_output: button.and_then(|b| b.log_output).unwrap_or(false),
     }
+}
 
-    fn input_stop_gesture(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
-        self.pointer_engaged = false;
-        self.pointer_location = None; // TODO: maybe not
-        Some(libwaylandsfpanel::RenderEvent::Render)
+/// Picks the tap action of a button, toggles take precedence over commands
+/// and commands over keys
+fn tap_action(index: usize, button: &Button) -> Result<Option<ClickHandler>, String> {
+    let configured = [
+        button.toggle.is_some(),
+        button.command.is_some(),
+        button.argv.is_some(),
+        button.keys.is_some(),
+        button.key_combo.is_some(),
+    ];
+    if configured.iter().filter(|&&set| set).count() > 1 {
+        return Err(format!(
+            "button '{}' can only have one of toggle, command, argv, keys and key_combo",
+            button.text
+        ));
     }
 
-    fn input_movement(
-        &mut self,
-        pos: libwaylandsfpanel::PointerPosition,
-    ) -> Option<libwaylandsfpanel::RenderEvent> {
-        self.pointer_location = Some(pos);
-        Some(libwaylandsfpanel::RenderEvent::Render)
+    if let Some(toggle) = &button.toggle {
+        return Ok(Some(ClickHandler::Toggle {
+            button: index,
+            on: shell_launch(Some(button), &toggle.on_command),
+            off: shell_launch(Some(button), &toggle.off_command),
+        }));
     }
-    fn input_commit_gesture(&mut self) -> Option<libwaylandsfpanel::RenderEvent> {
-        self.check_execute_click();
-        self.pointer_engaged = false;
-        Some(libwaylandsfpanel::RenderEvent::Render)
+
+    match (&button.command, &button.argv) {
+        (Some(command), _) => {
+            return Ok(Some(ClickHandler::RunCommand(shell_launch(
+                Some(button),
+                command,
+            ))))
+        }
+        (None, Some(argv)) if argv.is_empty() => {
+            return Err(format!("button '{}' has an empty argv", button.text))
+        }
+        (None, Some(argv)) => {
+            return Ok(Some(ClickHandler::RunCommand(launch(
+                Some(button),
+                argv.join(" "),
+                argv.clone(),
+            ))))
+        }
+        (None, None) => {}
     }
-}
 
-#[derive(Clone)]
-enum ClickHandler {
-    /// Run command
-    RunCommand(String),
+    let keys = match (&button.keys, &button.key_combo) {
+        (Some(keys), _) => keys.clone(),
+        (None, Some(combo)) => vec![combo.clone()],
+        (None, None) if button.kind != ButtonKind::Button => return Ok(None),
+        (None, None) => {
+            return Err(format!(
+                "button '{}' needs a command, argv, keys or key_combo",
+                button.text
+            ))
+        }
+    };
+
+    keys.iter()
+        .map(|key| key.parse())
+        .collect::<Result<_, _>>()
+        .map(|keys| Some(ClickHandler::PressKeys(keys)))
+        .map_err(|e| format!("button '{}': {}", button.text, e))
 }
 
 struct ClickTarget {
     position: (usize, usize),
     size: (usize, usize),
-    handler: ClickHandler,
+    /// Index of the button
+    button: usize,
 }
 
 impl ClickTarget {
-    fn process_click(
-        &self,
-        click_position: libwaylandsfpanel::PointerPosition,
-    ) -> Option<ClickHandler> {
+    fn rect(&self) -> Rect {
+        Rect::new(
+            self.position.0 as i32,
+            self.position.1 as i32,
+            self.size.0 as u32,
+            self.size.1 as u32,
+        )
+    }
+
+    fn process_click(&self, click_position: libwaylandsfpanel::PointerPosition) -> Option<usize> {
         let click_x = click_position.0;
         let click_y = click_position.1;
 
@@ -263,14 +2611,15 @@ impl ClickTarget {
             && click_y >= position_y
             && click_y < position_y + size_y
         {
-            Some(self.handler.clone())
+            Some(self.button)
         } else {
             None
         }
     }
 }
 
-pub fn parse_bar(args: impl Iterator<Item = String>) -> Result<Config, String> {
+/// Applies the logging options, returns the path of the configuration file
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<String, String> {
     let mut config_file = String::from("/etc/ppkui/bar.conf");
 
     // skip the binary name
@@ -281,37 +2630,46 @@ pub fn parse_bar(args: impl Iterator<Item = String>) -> Result<Config, String> {
         match args.next().as_deref() {
             // config file location
             Some("-c") | Some("--config") => {
-                let arg = args.next();
-
-                if arg.is_some() {
-                    config_file = arg.unwrap();
+                if let Some(arg) = args.next() {
+                    config_file = arg;
                 }
             }
 
+            // protocol event tracing, see also PPKUI_LOG
+            Some("-d") | Some("--debug") => libwaylandsfpanel::enable_debug(),
+
+            Some("-v") | Some("--verbose") => libwaylandsfpanel::increase_verbosity(),
+
+            Some("-q") | Some("--quiet") => libwaylandsfpanel::quiet(),
+
+            // stderr, syslog or journald
+            Some("--log-target") => {
+                let target = args.next().unwrap_or_default().parse()?;
+                libwaylandsfpanel::set_log_target(target);
+            }
+
             Some(arg) => return Err(format!("invalid arg '{}'", arg)),
 
             None => break,
         }
     }
 
-    let mut config_data = Vec::new();
-    std::fs::File::open(config_file)
-        .unwrap()
-        .read_to_end(&mut config_data)
-        .unwrap();
+    Ok(config_file)
+}
 
-    let mut config: Config = toml::from_slice(config_data.as_slice()).unwrap();
+pub fn parse_bar(config_file: &str) -> Result<Config, String> {
+    let mut config = Config::load(config_file)?;
 
     let mut bar_config = match config.bar {
-        None => return Err(format!("Bar section is not present")),
+        None => return Err("Bar section is not present".into()),
         Some(x) => x,
     };
 
-    if bar_config.font == "" {
+    if bar_config.font.is_empty() {
         bar_config.font = String::from("./assets/panel.ttf");
     };
 
-    if bar_config.buttons.len() == 0 {
+    if bar_config.buttons.is_empty() {
         return Err("no buttons defined".into());
     }
 
@@ -323,3 +2681,81 @@ pub fn parse_bar(args: impl Iterator<Item = String>) -> Result<Config, String> {
 fn main() {
     libwaylandsfpanel::run_application::<Bar>();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(text_color: [u8; 4]) -> Style {
+        let color = [255, 0, 0, 0];
+
+        Style {
+            colors: ColorConfig {
+                text_color,
+                button_color: color,
+                button_hover_color: color,
+                button_pressed_color: color,
+                background_color: color,
+                separator_color: color,
+                urgent_color: color,
+            },
+            font: load_font(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/panel.ttf")).unwrap(),
+            font_size: 12.,
+        }
+    }
+
+    #[test]
+    fn battery_styles() {
+        let (critical, low) = ([255, 255, 0, 0], [255, 255, 255, 0]);
+        let widget = BatteryWidget {
+            path: PathBuf::new(),
+            interval: Duration::from_secs(60),
+            charging_text: "+".to_owned(),
+            levels: [(10, style(critical)), (25, style(low))],
+        };
+
+        let text_color = |capacity, charging| {
+            widget
+                .style(Battery { capacity, charging })
+                .map(|style| style.colors.text_color)
+        };
+        assert_eq!(text_color(0, false), Some(critical));
+        assert_eq!(text_color(9, false), Some(critical));
+        assert_eq!(text_color(10, false), Some(low));
+        assert_eq!(text_color(24, false), Some(low));
+        assert_eq!(text_color(25, false), None);
+        assert_eq!(text_color(100, false), None);
+        assert_eq!(text_color(5, true), None);
+
+        assert_eq!(
+            widget.text(Battery {
+                capacity: 5,
+                charging: true
+            }),
+            "5%+"
+        );
+        assert_eq!(
+            widget.text(Battery {
+                capacity: 80,
+                charging: false
+            }),
+            "80%"
+        );
+    }
+
+    #[test]
+    fn status_buttons() {
+        let start = libwaylandsfpanel::PointerPosition(100., 10.);
+        let button = |dx, dy, held| {
+            let end = libwaylandsfpanel::PointerPosition(100. + dx, 10. + dy);
+            status_button(start, end, Duration::from_millis(held), 15.)
+        };
+
+        assert_eq!(button(3., 2., 100), 1);
+        assert_eq!(button(0., 0., LONG_PRESS_MS), 3);
+        assert_eq!(button(0., -20., 100), 4);
+        assert_eq!(button(5., 20., 1000), 5);
+        assert_eq!(button(-20., 0., 100), 6);
+        assert_eq!(button(20., 5., 100), 7);
+    }
+}
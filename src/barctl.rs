@@ -0,0 +1,62 @@
+mod control_socket;
+
+use std::{
+    env,
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    process,
+};
+
+const USAGE: &str = "usage: ppkui_barctl <command> [arguments]
+
+commands:
+  set-label <button> <text>
+  set-color <button> [button_color|button_hover_color|button_pressed_color|text_color] <color>
+  show
+  hide
+  press-button <button>
+  reload-config
+  list-buttons
+
+Buttons are given by their name, their index or their text. Quoted
+arguments keep all of their spaces.";
+
+fn main() {
+    let request: Vec<String> = env::args().skip(1).collect();
+    if request.is_empty() || request[0] == "--help" || request[0] == "-h" {
+        eprintln!("{}", USAGE);
+
+        process::exit(2);
+    }
+
+    let path = match control_socket::path() {
+        Some(path) => path,
+        None => {
+            eprintln!("XDG_RUNTIME_DIR is not set");
+
+            process::exit(1);
+        }
+    };
+
+    let mut reply = String::new();
+    let result = UnixStream::connect(&path).and_then(|mut stream| {
+        // tabs keep the spaces within the arguments
+        writeln!(stream, "{}", request.join("\t"))?;
+        stream.shutdown(Shutdown::Write)?;
+        stream.read_to_string(&mut reply)
+    });
+    if let Err(e) = result {
+        eprintln!("can't talk to ppkui_bar at '{}': {}", path.display(), e);
+
+        process::exit(1);
+    }
+
+    if reply.starts_with("error: ") {
+        eprint!("{}", reply);
+
+        process::exit(1);
+    }
+
+    print!("{}", reply);
+}
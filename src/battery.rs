@@ -0,0 +1,129 @@
+//! Battery state from sysfs, see the kernel's sysfs-class-power ABI
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Battery {
+    /// Percentage
+    pub capacity: u32,
+    pub charging: bool,
+}
+
+/// Directory of the named power supply, or of the first battery
+pub fn find(dir: &Path, supply: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(supply) = supply {
+        return Ok(dir.join(supply));
+    }
+
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("can't list '{}': {}", dir.display(), e))?;
+
+    // the order of the entries is arbitrary
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| read_value(path, "type").is_ok_and(|kind| kind == "Battery"))
+        .min()
+        .ok_or_else(|| format!("no battery in '{}'", dir.display()))
+}
+
+pub fn read(path: &Path) -> io::Result<Battery> {
+    let capacity = read_value(path, "capacity")?
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid capacity"))?;
+
+    // not every driver knows whether it's charging
+    let charging = read_value(path, "status").is_ok_and(|status| status == "Charging");
+
+    Ok(Battery { capacity, charging })
+}
+
+fn read_value(path: &Path, name: &str) -> io::Result<String> {
+    fs::read_to_string(path.join(name)).map(|value| value.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process};
+
+    /// A power supply tree of its own for each test
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("ppkui-battery-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            Tree(dir)
+        }
+
+        fn supply(&self, name: &str, values: &[(&str, &str)]) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir(&path).unwrap();
+
+            for (name, value) in values {
+                fs::write(path.join(name), format!("{}\n", value)).unwrap();
+            }
+
+            path
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_the_first_battery() {
+        let tree = Tree::new("first");
+        tree.supply("AC", &[("type", "Mains")]);
+        tree.supply("BAT1", &[("type", "Battery")]);
+        let first = tree.supply("BAT0", &[("type", "Battery")]);
+
+        assert_eq!(find(&tree.0, None), Ok(first));
+        assert_eq!(find(&tree.0, Some("BAT1")), Ok(tree.0.join("BAT1")));
+    }
+
+    #[test]
+    fn no_battery() {
+        let tree = Tree::new("none");
+        tree.supply("AC", &[("type", "Mains")]);
+
+        assert!(find(&tree.0, None)
+            .unwrap_err()
+            .starts_with("no battery in"));
+    }
+
+    #[test]
+    fn reads_the_state() {
+        let tree = Tree::new("state");
+        let charging = tree.supply("BAT0", &[("capacity", "57"), ("status", "Charging")]);
+        let discharging = tree.supply("BAT1", &[("capacity", "100"), ("status", "Full")]);
+        // no status at all
+        let unknown = tree.supply("BAT2", &[("capacity", "3")]);
+
+        let battery = |capacity, charging| Battery { capacity, charging };
+        assert_eq!(read(&charging).unwrap(), battery(57, true));
+        assert_eq!(read(&discharging).unwrap(), battery(100, false));
+        assert_eq!(read(&unknown).unwrap(), battery(3, false));
+    }
+
+    #[test]
+    fn rejects_a_bad_capacity() {
+        let tree = Tree::new("bad");
+        let bad = tree.supply("BAT0", &[("capacity", "lots")]);
+        let missing = tree.supply("BAT1", &[("status", "Charging")]);
+
+        assert_eq!(read(&bad).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(read(&missing).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,123 @@
+//! Local time for the clock buttons
+
+use nix::libc::{c_char, size_t, time_t, tm};
+
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Conversion specifiers which change every second
+const SECOND_SPECIFIERS: &[char] = &['S', 'T', 'r', 's', 'X', 'c', '+'];
+
+/// Extra delay after a boundary, so that the timer never wakes up too early
+const TICK_MARGIN_MS: u64 = 10;
+
+extern "C" {
+    fn tzset();
+    fn strftime(s: *mut c_char, max: size_t, format: *const c_char, tm: *const tm) -> size_t;
+}
+
+/// Picks up the time zone from `TZ` or the system configuration
+pub fn init() {
+    unsafe { tzset() };
+}
+
+/// Formats the current local time like strftime(3)
+pub fn format(format: &str) -> String {
+    let format = match CString::new(format) {
+        Ok(format) => format,
+        Err(..) => return String::new(),
+    };
+
+    let mut buffer = [0u8; 256];
+    let len = unsafe {
+        let now = nix::libc::time(std::ptr::null_mut::<time_t>());
+        let mut local = MaybeUninit::<tm>::zeroed();
+        nix::libc::localtime_r(&now, local.as_mut_ptr());
+
+        strftime(
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer.len(),
+            format.as_ptr(),
+            local.as_ptr(),
+        )
+    };
+
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+/// How often the text of a format changes, one second or one minute
+pub fn period(format: &str) -> Duration {
+    let mut chars = format.chars();
+    let mut changes_every_second = false;
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+
+        // skip flags, widths and modifiers like in `%-S` or `%OS`, a `%%`
+        // takes the second percent sign along
+        let conversion = chars
+            .by_ref()
+            .find(|c| !"_-0^#EO".contains(*c) && !c.is_ascii_digit());
+        if conversion.is_some_and(|c| SECOND_SPECIFIERS.contains(&c)) {
+            changes_every_second = true;
+            break;
+        }
+    }
+
+    match changes_every_second {
+        true => Duration::from_secs(1),
+        false => Duration::from_secs(60),
+    }
+}
+
+/// Time until just after the next multiple of `period`
+pub fn until_next(period: Duration) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let period = period.as_millis() as u64;
+
+    Duration::from_millis(period - now % period + TICK_MARGIN_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods() {
+        let minute = Duration::from_secs(60);
+        let second = Duration::from_secs(1);
+
+        assert_eq!(period("%H:%M"), minute);
+        assert_eq!(period("%a %d %b"), minute);
+        assert_eq!(period("%T"), second);
+        assert_eq!(period("%H:%M:%-S"), second);
+        assert_eq!(period("%OS"), second);
+        assert_eq!(period("%_2S"), second);
+        assert_eq!(period("%%%S"), second);
+    }
+
+    #[test]
+    fn escaped_percent_signs() {
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(period("%%S"), minute);
+        assert_eq!(period("100%%Score %H:%M"), minute);
+        assert_eq!(period("50%"), minute);
+    }
+
+    #[test]
+    fn next_tick() {
+        for period in [Duration::from_secs(1), Duration::from_secs(60)] {
+            let until_next = until_next(period);
+
+            assert!(until_next > Duration::ZERO);
+            assert!(until_next <= period + Duration::from_millis(TICK_MARGIN_MS));
+        }
+    }
+}
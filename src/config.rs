@@ -1,14 +1,22 @@
 use serde_derive::Deserialize;
 
-use std::str::FromStr;
+use std::{collections::HashMap, fs, str::FromStr};
 
 use css_color::Rgba;
+use log::debug;
 
 #[derive(Deserialize, Clone)]
 pub struct Config {
     button_color: String,
     button_hover_color: String,
+    /// `button_hover_color` by default
+    button_pressed_color: Option<String>,
     text_color: String,
+    background_color: Option<String>,
+    /// `text_color` by default
+    separator_color: Option<String>,
+    /// Background of urgent status blocks, dark red by default
+    urgent_color: Option<String>,
     pub bar: Option<BarConfig>,
     pub launcher: Option<LauncherConfig>,
 }
@@ -17,9 +25,50 @@ pub struct Config {
 pub struct BarConfig {
     pub height: u32,
     pub font: String,
+    /// Text size in pixels, half of the height by default
+    pub font_size: Option<f32>,
+    /// Replace the global colors for the bar
+    #[serde(flatten)]
+    pub colors: ColorOverrides,
+    /// Used for icons given by name, "hicolor" by default
+    pub icon_theme: Option<String>,
+    /// Space at both ends of the bar
+    pub padding: Option<u32>,
+    /// Space between buttons
+    pub spacing: Option<u32>,
+    /// Width of the lines drawn between buttons, none by default
+    pub separator_width: Option<u32>,
+    /// Shell command speaking the i3bar protocol, like `i3status`
+    pub status_command: Option<String>,
+    /// Where the status is placed, right by default
+    pub status_group: Option<Group>,
+    /// Shell commands run by horizontal swipes along the whole bar, which
+    /// don't tap the buttons then
+    pub swipe_left_command: Option<String>,
+    pub swipe_right_command: Option<String>,
+    /// Swipes along the bar without a command switch between the windows
+    #[serde(default)]
+    pub swipe_windows: bool,
+    /// Distance of swipes along the bar in pixels, three times the height by
+    /// default
+    pub swipe_threshold: Option<u32>,
+    /// Collapse the bar to a handle, which a tap or a swipe up expands
+    pub auto_hide: Option<AutoHideConfig>,
     pub buttons: Vec<Button>,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct AutoHideConfig {
+    /// Height of the collapsed bar, 4 by default
+    pub handle_height: Option<u32>,
+    /// Space kept free for windows, none by default
+    pub exclusive_zone: Option<u32>,
+    /// Milliseconds without a touch before the bar collapses, 3000 by default
+    pub timeout_ms: Option<u64>,
+    /// Duration of the slide animation, 150 by default
+    pub slide_ms: Option<u64>,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct LauncherConfig {
     pub prompt: Option<String>,
@@ -28,34 +77,375 @@ pub struct LauncherConfig {
     pub font: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ColorConfig {
     pub text_color: [u8; 4],
     pub button_color: [u8; 4],
     pub button_hover_color: [u8; 4],
+    pub button_pressed_color: [u8; 4],
+    pub background_color: [u8; 4],
+    pub separator_color: [u8; 4],
+    pub urgent_color: [u8; 4],
 }
 
 #[derive(Deserialize, Clone)]
 pub struct Button {
+    #[serde(rename = "type", default)]
+    pub kind: ButtonKind,
+    /// Identifies the button in requests to the control socket
+    pub name: Option<String>,
+    /// May be left out for icon buttons
+    #[serde(default)]
     pub text: String,
-    pub command: String,
+    /// Time format of clocks like strftime(3), `%H:%M` by default
+    pub format: Option<String>,
+    /// Shell command run periodically, the first line it prints replaces `text`
+    pub label_command: Option<String>,
+    /// Seconds between the runs of `label_command`, 10 by default
+    pub interval: Option<u64>,
+    /// Path to a png or svg file, or the name of a themed icon
+    pub icon: Option<String>,
+    /// Draw the icon in `text_color`, true by default
+    pub icon_tint: Option<bool>,
+    /// Replace the colors of the bar for this button
+    #[serde(flatten)]
+    pub colors: ColorOverrides,
+    /// Font file used instead of the one of the bar
+    pub font: Option<String>,
+    /// Text size in pixels
+    pub font_size: Option<f32>,
+    /// Shell command
+    pub command: Option<String>,
+    /// Program and its arguments, executed without a shell
+    pub argv: Option<Vec<String>>,
+    /// Additional environment variables of the command
+    pub env: Option<HashMap<String, String>>,
+    /// Working directory of the command
+    pub cwd: Option<String>,
+    /// Key combos pressed one after another, like `["Escape"]`
+    pub keys: Option<Vec<String>>,
+    /// Single key combo, like `"ctrl+w"`
+    pub key_combo: Option<String>,
+    /// Ignore taps while the command is still running
+    pub single_instance: Option<bool>,
+    /// Log the exit status and stderr of the command
+    pub log_output: Option<bool>,
+    /// Shell command run when the button is held
+    pub long_press_command: Option<String>,
+    /// How long the button has to be held, 500 by default
+    pub long_press_ms: Option<u64>,
+    /// Shell commands run when a swipe starts on the button
+    pub swipe_up_command: Option<String>,
+    pub swipe_down_command: Option<String>,
+    pub swipe_left_command: Option<String>,
+    pub swipe_right_command: Option<String>,
+    /// Keep running the action while the button is held
+    pub repeat: Option<RepeatConfig>,
+    /// Switch between on and off on each tap instead of running `command`
+    pub toggle: Option<ToggleConfig>,
+    /// Settings of battery widgets
+    pub battery: Option<BatteryConfig>,
+    /// Settings of workspace widgets
+    pub workspaces: Option<WorkspacesConfig>,
+    /// Settings of task widgets
+    pub tasks: Option<TasksConfig>,
+    /// Fixed width in pixels
+    pub width: Option<u32>,
+    /// Share of the free space if there's no fixed width, 1 by default
+    pub weight: Option<u32>,
+    pub group: Option<Group>,
+    /// Empty space instead of a button, flexible unless a width is given
+    pub spacer: Option<bool>,
+}
+
+/// What a button shows
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ButtonKind {
+    /// `text` and `icon`
+    #[default]
+    Button,
+    /// The current time, a command is optional
+    Clock,
+    /// Charge of a battery, a command is optional
+    Battery,
+    /// A button for each sway workspace, taps switch to it
+    Workspaces,
+    /// A button for each window, taps activate it and long presses or swipes
+    /// up close it
+    Tasks,
+}
+
+/// Part of the bar a button is placed in. Without flexible buttons, left
+/// buttons start at the left edge, right buttons end at the right edge and
+/// center buttons are centered between them; otherwise the flexible buttons
+/// fill the gaps.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Group {
+    Left,
+    #[serde(alias = "centre")]
+    Center,
+    Right,
+}
+
+/// Optional replacements of the button colors
+#[derive(Deserialize, Clone, Default)]
+pub struct ColorOverrides {
+    pub button_color: Option<String>,
+    pub button_hover_color: Option<String>,
+    pub button_pressed_color: Option<String>,
+    pub text_color: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ToggleConfig {
+    /// Shell commands run when the button is turned on and off
+    pub on_command: String,
+    pub off_command: String,
+    /// Shell command which succeeds if the toggle is on, run on start
+    pub state_command: Option<String>,
+    /// Seconds between the runs of `state_command`, it's only run on start
+    /// by default
+    pub interval: Option<u64>,
+    #[serde(default)]
+    pub on: ToggleStyle,
+    #[serde(default)]
+    pub off: ToggleStyle,
+}
+
+/// Look of a toggle button in one of its states
+#[derive(Deserialize, Clone, Default)]
+pub struct ToggleStyle {
+    /// Replaces the `text` of the button
+    pub text: Option<String>,
+    #[serde(flatten)]
+    pub colors: ColorOverrides,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BatteryConfig {
+    /// Name of the power supply, the first battery by default
+    pub supply: Option<String>,
+    /// Where the power supplies are, `/sys/class/power_supply` by default
+    pub power_supply_dir: Option<String>,
+    /// Seconds between reads, besides the ones caused by power supply
+    /// events, 60 by default
+    pub interval: Option<u64>,
+    /// Added to the percentage while charging, `+` by default
+    pub charging_text: Option<String>,
+    #[serde(default = "BatteryLevel::low")]
+    pub low: BatteryLevel,
+    #[serde(default = "BatteryLevel::critical")]
+    pub critical: BatteryLevel,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig {
+            supply: None,
+            power_supply_dir: None,
+            interval: None,
+            charging_text: None,
+            low: BatteryLevel::low(),
+            critical: BatteryLevel::critical(),
+        }
+    }
+}
+
+/// Look of a battery which isn't charging below a capacity
+#[derive(Deserialize, Clone)]
+pub struct BatteryLevel {
+    /// Percentage
+    pub below: u32,
+    #[serde(flatten)]
+    pub colors: ColorOverrides,
+}
+
+impl BatteryLevel {
+    fn low() -> Self {
+        BatteryLevel {
+            below: 20,
+            colors: ColorOverrides {
+                text_color: Some("#ffa000".to_owned()),
+                ..ColorOverrides::default()
+            },
+        }
+    }
+
+    fn critical() -> Self {
+        BatteryLevel {
+            below: 10,
+            colors: ColorOverrides {
+                button_color: Some("#900000".to_owned()),
+                ..ColorOverrides::default()
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WorkspacesConfig {
+    /// Path of the sway IPC socket, `$SWAYSOCK` by default
+    pub socket: Option<String>,
+    #[serde(default = "WorkspacesConfig::focused")]
+    pub focused: ColorOverrides,
+    #[serde(default = "WorkspacesConfig::urgent")]
+    pub urgent: ColorOverrides,
+}
+
+impl WorkspacesConfig {
+    fn focused() -> ColorOverrides {
+        ColorOverrides {
+            button_color: Some("#285577".to_owned()),
+            ..ColorOverrides::default()
+        }
+    }
+
+    fn urgent() -> ColorOverrides {
+        ColorOverrides {
+            button_color: Some("#900000".to_owned()),
+            ..ColorOverrides::default()
+        }
+    }
+}
+
+impl Default for WorkspacesConfig {
+    fn default() -> Self {
+        WorkspacesConfig {
+            socket: None,
+            focused: WorkspacesConfig::focused(),
+            urgent: WorkspacesConfig::urgent(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TasksConfig {
+    /// Show the window titles instead of the app ids
+    #[serde(default)]
+    pub show_title: bool,
+    #[serde(default = "TasksConfig::active")]
+    pub active: ColorOverrides,
+}
+
+impl TasksConfig {
+    fn active() -> ColorOverrides {
+        ColorOverrides {
+            button_color: Some("#285577".to_owned()),
+            ..ColorOverrides::default()
+        }
+    }
+}
+
+impl Default for TasksConfig {
+    fn default() -> Self {
+        TasksConfig {
+            show_title: false,
+            active: TasksConfig::active(),
+        }
+    }
 }
 
+#[derive(Deserialize, Clone)]
+pub struct RepeatConfig {
+    /// Before the first repetition, 500 by default
+    pub delay_ms: Option<u64>,
+    /// Between repetitions, 100 by default
+    pub interval_ms: Option<u64>,
+}
+
+pub fn parse_color(name: &str, value: &str) -> Result<[u8; 4], String> {
+    Rgba::from_str(value)
+        .map(rgba_to_color)
+        .map_err(|_| format!("invalid {} '{}'", name, value))
+}
+
+/// Converts a color to premultiplied `[a, r, g, b]`, which is what
+/// `wl_shm::Format::Argb8888` expects
 fn rgba_to_color(i: Rgba) -> [u8; 4] {
-    return [
-        (i.alpha * 255.) as u8,
-        (i.red * 255.) as u8,
-        (i.green * 255.) as u8,
-        (i.blue * 255.) as u8,
-    ];
+    [
+        (i.alpha * 255.).round() as u8,
+        (i.red * i.alpha * 255.).round() as u8,
+        (i.green * i.alpha * 255.).round() as u8,
+        (i.blue * i.alpha * 255.).round() as u8,
+    ]
+}
+
+impl ColorConfig {
+    /// Everything is drawn on top of the background, so a surface is fully
+    /// opaque as long as the background is
+    pub fn is_opaque(&self) -> bool {
+        self.background_color[0] == 255
+    }
+
+    /// Applies overrides on top of these colors
+    pub fn with_overrides(&self, overrides: &ColorOverrides) -> Result<ColorConfig, String> {
+        let color = |name, value: &Option<String>, default| match value {
+            Some(value) => parse_color(name, value),
+            None => Ok(default),
+        };
+
+        let button_hover_color = color(
+            "button_hover_color",
+            &overrides.button_hover_color,
+            self.button_hover_color,
+        )?;
+        // a new hover color is used for pressing as well, unless given too
+        let pressed_default = match overrides.button_hover_color {
+            Some(_) => button_hover_color,
+            None => self.button_pressed_color,
+        };
+
+        Ok(ColorConfig {
+            text_color: color("text_color", &overrides.text_color, self.text_color)?,
+            button_color: color("button_color", &overrides.button_color, self.button_color)?,
+            button_hover_color,
+            button_pressed_color: color(
+                "button_pressed_color",
+                &overrides.button_pressed_color,
+                pressed_default,
+            )?,
+            ..self.clone()
+        })
+    }
 }
 
 impl Config {
-    pub fn get_color_config(&self) -> ColorConfig {
-        ColorConfig {
-            text_color: rgba_to_color(Rgba::from_str(&self.text_color).unwrap()),
-            button_color: rgba_to_color(Rgba::from_str(&self.button_color).unwrap()),
-            button_hover_color: rgba_to_color(Rgba::from_str(&self.button_hover_color).unwrap()),
-        }
+    pub fn load(path: &str) -> Result<Config, String> {
+        debug!("loading config from {}", path);
+
+        let config_data =
+            fs::read(path).map_err(|e| format!("can't read config '{}': {}", path, e))?;
+
+        toml::from_slice(config_data.as_slice())
+            .map_err(|e| format!("can't parse config '{}': {}", path, e))
+    }
+
+    pub fn get_color_config(&self) -> Result<ColorConfig, String> {
+        let text_color = parse_color("text_color", &self.text_color)?;
+        let button_hover_color = parse_color("button_hover_color", &self.button_hover_color)?;
+
+        Ok(ColorConfig {
+            text_color,
+            button_color: parse_color("button_color", &self.button_color)?,
+            button_hover_color,
+            button_pressed_color: match &self.button_pressed_color {
+                Some(color) => parse_color("button_pressed_color", color)?,
+                None => button_hover_color,
+            },
+            background_color: parse_color(
+                "background_color",
+                self.background_color.as_deref().unwrap_or("transparent"),
+            )?,
+            separator_color: match &self.separator_color {
+                Some(color) => parse_color("separator_color", color)?,
+                None => text_color,
+            },
+            urgent_color: parse_color(
+                "urgent_color",
+                self.urgent_color.as_deref().unwrap_or("#900000"),
+            )?,
+        })
     }
 }
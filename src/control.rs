@@ -0,0 +1,198 @@
+//! Requests to a running bar through its control socket, one line each.
+//!
+//! ppkui_barctl separates the words with tabs, so the text of a label keeps
+//! all of its spaces. A line without tabs is split at spaces instead, which
+//! is easier to type by hand.
+
+use std::str::FromStr;
+
+/// Buttons are given by their `name`, their index or their `text`
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    SetLabel {
+        button: String,
+        text: String,
+    },
+    /// The color is one of the button colors, `button_color` by default
+    SetColor {
+        button: String,
+        name: String,
+        color: String,
+    },
+    Show,
+    Hide,
+    PressButton {
+        button: String,
+    },
+    ReloadConfig,
+    ListButtons,
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (line, separator) = match line.contains('\t') {
+            true => (line.trim_end_matches(['\r', '\n']), '\t'),
+            false => (line.trim(), ' '),
+        };
+
+        let (command, arguments) = split_word(line, separator);
+        let (button, rest) = split_word(arguments, separator);
+
+        let request = match command {
+            // separate words of the text are joined like `echo` does
+            "set-label" => Request::SetLabel {
+                button: button.to_owned(),
+                text: rest.replace('\t', " "),
+            },
+            "set-color" => {
+                let (name, color) = match split_word(rest, separator) {
+                    (color, "") => ("button_color", color),
+                    (name, color) => (name, color),
+                };
+
+                Request::SetColor {
+                    button: button.to_owned(),
+                    name: name.to_owned(),
+                    color: color.to_owned(),
+                }
+            }
+            "press-button" => Request::PressButton {
+                button: button.to_owned(),
+            },
+            "show" => Request::Show,
+            "hide" => Request::Hide,
+            "reload-config" => Request::ReloadConfig,
+            "list-buttons" => Request::ListButtons,
+            "" => return Err("empty request".to_owned()),
+            _ => return Err(format!("unknown command '{}'", command)),
+        };
+
+        let missing_button = match &request {
+            Request::SetLabel { button, .. } | Request::PressButton { button } => button.is_empty(),
+            Request::SetColor { button, color, .. } => button.is_empty() || color.is_empty(),
+            _ => false,
+        };
+        if missing_button {
+            return Err(format!("'{}' needs more arguments", command));
+        }
+
+        Ok(request)
+    }
+}
+
+/// Splits off the first word, the rest keeps its inner spaces. Repeated
+/// spaces count as one separator, tabs don't.
+fn split_word(s: &str, separator: char) -> (&str, &str) {
+    match s.find(separator) {
+        Some(i) if separator == ' ' => (&s[..i], s[i + 1..].trim_start()),
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Request, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn set_color_defaults_to_the_button_color() {
+        assert_eq!(
+            parse("set-color clock #ff0000"),
+            Ok(Request::SetColor {
+                button: "clock".into(),
+                name: "button_color".into(),
+                color: "#ff0000".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn set_color_with_a_color_name() {
+        assert_eq!(
+            parse("set-color 2 text_color red"),
+            Ok(Request::SetColor {
+                button: "2".into(),
+                name: "text_color".into(),
+                color: "red".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn set_label_keeps_inner_spaces() {
+        assert_eq!(
+            parse("set-label clock  lunch break \n"),
+            Ok(Request::SetLabel {
+                button: "clock".into(),
+                text: "lunch break".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn tabs_keep_every_space() {
+        assert_eq!(
+            parse("set-label\tclock\t  lunch   break \n"),
+            Ok(Request::SetLabel {
+                button: "clock".into(),
+                text: "  lunch   break ".into(),
+            })
+        );
+        assert_eq!(
+            parse("set-label\tclock\tlunch\tbreak"),
+            Ok(Request::SetLabel {
+                button: "clock".into(),
+                text: "lunch break".into(),
+            })
+        );
+        assert_eq!(
+            parse("set-color\tclock\ttext_color\tred"),
+            Ok(Request::SetColor {
+                button: "clock".into(),
+                name: "text_color".into(),
+                color: "red".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn requests_without_arguments() {
+        assert_eq!(parse("show"), Ok(Request::Show));
+        assert_eq!(parse("hide\n"), Ok(Request::Hide));
+        assert_eq!(parse("reload-config"), Ok(Request::ReloadConfig));
+        assert_eq!(parse("list-buttons"), Ok(Request::ListButtons));
+        assert_eq!(
+            parse("press-button 0"),
+            Ok(Request::PressButton { button: "0".into() })
+        );
+    }
+
+    #[test]
+    fn missing_arguments() {
+        let error = Err("'set-label' needs more arguments".to_owned());
+        assert_eq!(parse("set-label"), error);
+        assert_eq!(
+            parse("set-color clock"),
+            Err("'set-color' needs more arguments".to_owned())
+        );
+        assert_eq!(
+            parse("press-button"),
+            Err("'press-button' needs more arguments".to_owned())
+        );
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(parse(""), Err("empty request".to_owned()));
+        assert_eq!(
+            parse("explode now"),
+            Err("unknown command 'explode'".to_owned())
+        );
+    }
+}
@@ -0,0 +1,12 @@
+//! Location of the control socket, shared by the bar and ppkui_barctl
+
+use std::env;
+use std::path::PathBuf;
+
+/// `$PPKUI_BAR_SOCKET`, or `ppkui_bar.sock` in `$XDG_RUNTIME_DIR`
+pub fn path() -> Option<PathBuf> {
+    match env::var_os("PPKUI_BAR_SOCKET") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("ppkui_bar.sock")),
+    }
+}
@@ -0,0 +1,340 @@
+//! Horizontal layout of the bar items
+
+use crate::config::Group;
+
+use std::cmp;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Size {
+    /// Width in pixels
+    Fixed(u32),
+    /// Share of the space left over by everything else
+    Flex(u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Item {
+    pub size: Size,
+    pub group: Group,
+    /// Gets a separator between itself and its neighbours in the same group,
+    /// if they have one as well
+    pub separated: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Space at both ends of the bar
+    pub padding: u32,
+    /// Space between neighbouring items
+    pub spacing: u32,
+    /// Width of separators, 0 disables them
+    pub separator: u32,
+}
+
+/// Horizontal position of an item or separator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub x: i32,
+    pub width: u32,
+}
+
+pub struct Layout {
+    /// One span for each item
+    pub items: Vec<Span>,
+    pub separators: Vec<Span>,
+}
+
+/// Places items in a bar of the given width.
+///
+/// Flexible items share the remaining space by weight; the pixels left over
+/// by rounding go one by one to the items which lost the most to it, so the
+/// items always cover the whole bar exactly.
+pub fn layout(items: &[Item], width: u32, options: &Options) -> Layout {
+    let groups: Vec<Vec<usize>> = [Group::Left, Group::Center, Group::Right]
+        .iter()
+        .map(|group| {
+            (0..items.len())
+                .filter(|i| items[*i].group == *group)
+                .collect()
+        })
+        .collect();
+
+    // everything except for the flexible items
+    let used_groups = groups.iter().filter(|group| !group.is_empty()).count() as i64;
+    let mut fixed = 2 * options.padding as i64 + options.spacing as i64 * (used_groups - 1).max(0);
+    for group in &groups {
+        fixed += gaps_width(items, group, options);
+        fixed += group
+            .iter()
+            .map(|i| match items[*i].size {
+                Size::Fixed(width) => width as i64,
+                Size::Flex(_) => 0,
+            })
+            .sum::<i64>();
+    }
+
+    let widths = item_widths(items, cmp::max(width as i64 - fixed, 0));
+
+    let group_width = |group: &[usize]| -> i64 {
+        group.iter().map(|i| widths[*i] as i64).sum::<i64>() + gaps_width(items, group, options)
+    };
+
+    let padding = options.padding as i64;
+    let spacing = options.spacing as i64;
+    let width = width as i64;
+
+    let left_end = padding + group_width(&groups[0]);
+    let right_start = width - padding - group_width(&groups[2]);
+    let center_width = group_width(&groups[1]);
+
+    // centered on the bar unless that overlaps another group
+    let mut center_start = (width - center_width) / 2;
+    if !groups[2].is_empty() {
+        center_start = cmp::min(center_start, right_start - spacing - center_width);
+    }
+    if !groups[0].is_empty() {
+        center_start = cmp::max(center_start, left_end + spacing);
+    }
+
+    let mut layout = Layout {
+        items: vec![Span { x: 0, width: 0 }; items.len()],
+        separators: vec![],
+    };
+
+    for (group, start) in groups.iter().zip(&[padding, center_start, right_start]) {
+        place_group(items, group, &widths, *start, options, &mut layout);
+    }
+
+    layout
+}
+
+/// Widths of the flexible items sharing `available` pixels, fixed items
+/// keep their own width
+fn item_widths(items: &[Item], available: i64) -> Vec<u32> {
+    let total_weight: i64 = items
+        .iter()
+        .map(|item| match item.size {
+            Size::Flex(weight) => weight as i64,
+            Size::Fixed(_) => 0,
+        })
+        .sum();
+
+    if total_weight == 0 {
+        return items
+            .iter()
+            .map(|item| match item.size {
+                Size::Fixed(width) => width,
+                Size::Flex(_) => 0,
+            })
+            .collect();
+    }
+
+    let mut widths = vec![];
+    // (remainder, index) of every flexible item
+    let mut remainders = vec![];
+
+    for (i, item) in items.iter().enumerate() {
+        match item.size {
+            Size::Fixed(width) => widths.push(width),
+            Size::Flex(weight) => {
+                let share = available * weight as i64;
+                widths.push((share / total_weight) as u32);
+                remainders.push((share % total_weight, i));
+            }
+        }
+    }
+
+    let assigned: i64 = remainders.iter().map(|(_, i)| widths[*i] as i64).sum();
+    let mut leftover = available - assigned;
+
+    // largest remainder first, earlier items win ties
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for (_, i) in remainders {
+        if leftover == 0 {
+            break;
+        }
+
+        widths[i] += 1;
+        leftover -= 1;
+    }
+
+    widths
+}
+
+fn has_separator(items: &[Item], a: usize, b: usize, options: &Options) -> bool {
+    options.separator > 0 && items[a].separated && items[b].separated
+}
+
+/// Total width of the space between the items of a group
+fn gaps_width(items: &[Item], group: &[usize], options: &Options) -> i64 {
+    group
+        .windows(2)
+        .map(|pair| {
+            let mut gap = options.spacing as i64;
+            if has_separator(items, pair[0], pair[1], options) {
+                gap += (options.separator + options.spacing) as i64;
+            }
+            gap
+        })
+        .sum()
+}
+
+fn place_group(
+    items: &[Item],
+    group: &[usize],
+    widths: &[u32],
+    start: i64,
+    options: &Options,
+    layout: &mut Layout,
+) {
+    let mut x = start;
+
+    for (n, i) in group.iter().enumerate() {
+        if n > 0 {
+            let previous = group[n - 1];
+            x += options.spacing as i64;
+
+            if has_separator(items, previous, *i, options) {
+                layout.separators.push(Span {
+                    x: x as i32,
+                    width: options.separator,
+                });
+                x += (options.separator + options.spacing) as i64;
+            }
+        }
+
+        layout.items[*i] = Span {
+            x: x as i32,
+            width: widths[*i],
+        };
+        x += widths[*i] as i64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(size: Size, group: Group) -> Item {
+        Item {
+            size,
+            group,
+            separated: false,
+        }
+    }
+
+    fn spans(layout: &Layout) -> Vec<(i32, u32)> {
+        layout
+            .items
+            .iter()
+            .map(|span| (span.x, span.width))
+            .collect()
+    }
+
+    #[test]
+    fn uneven_widths() {
+        let items = [item(Size::Flex(1), Group::Left); 3];
+        let layout = layout(&items, 100, &Options::default());
+
+        assert_eq!(spans(&layout), [(0, 34), (34, 33), (67, 33)]);
+    }
+
+    #[test]
+    fn fixed_and_flexible_items() {
+        let items = [
+            item(Size::Fixed(20), Group::Left),
+            item(Size::Flex(1), Group::Left),
+            item(Size::Flex(3), Group::Left),
+        ];
+        let layout = layout(&items, 100, &Options::default());
+
+        assert_eq!(spans(&layout), [(0, 20), (20, 20), (40, 60)]);
+    }
+
+    #[test]
+    fn groups() {
+        let items = [
+            item(Size::Fixed(30), Group::Right),
+            item(Size::Fixed(20), Group::Center),
+            item(Size::Fixed(10), Group::Left),
+        ];
+        let layout = layout(&items, 100, &Options::default());
+
+        assert_eq!(spans(&layout), [(70, 30), (40, 20), (0, 10)]);
+    }
+
+    #[test]
+    fn center_moves_away_from_a_wide_group() {
+        let items = [
+            item(Size::Fixed(50), Group::Left),
+            item(Size::Fixed(20), Group::Center),
+            item(Size::Fixed(10), Group::Right),
+        ];
+        let options = Options {
+            spacing: 2,
+            ..Options::default()
+        };
+        let layout = layout(&items, 100, &options);
+
+        assert_eq!(spans(&layout), [(0, 50), (52, 20), (90, 10)]);
+    }
+
+    #[test]
+    fn padding_spacing_and_separators() {
+        let separated = |size| Item {
+            separated: true,
+            ..item(size, Group::Left)
+        };
+        let items = [
+            separated(Size::Fixed(10)),
+            separated(Size::Fixed(10)),
+            item(Size::Fixed(10), Group::Left),
+            item(Size::Flex(1), Group::Left),
+        ];
+        let options = Options {
+            padding: 5,
+            spacing: 2,
+            separator: 1,
+        };
+        let layout = layout(&items, 100, &options);
+
+        assert_eq!(spans(&layout), [(5, 10), (20, 10), (32, 10), (44, 51)]);
+        assert_eq!(layout.separators, [Span { x: 17, width: 1 }]);
+    }
+
+    #[test]
+    fn bar_narrower_than_its_fixed_items() {
+        let items = [
+            item(Size::Fixed(60), Group::Left),
+            item(Size::Flex(1), Group::Left),
+            item(Size::Fixed(60), Group::Left),
+        ];
+        let layout = layout(&items, 100, &Options::default());
+
+        assert_eq!(spans(&layout), [(0, 60), (60, 0), (60, 60)]);
+    }
+
+    #[test]
+    fn leftover_pixels_cover_the_bar() {
+        let items = [
+            item(Size::Flex(1), Group::Left),
+            item(Size::Flex(2), Group::Left),
+            item(Size::Flex(4), Group::Left),
+        ];
+        let options = Options {
+            padding: 1,
+            spacing: 1,
+            separator: 0,
+        };
+
+        for width in 4..60 {
+            let layout = layout(&items, width, &options);
+            let last = layout.items[2];
+
+            assert_eq!(last.x as u32 + last.width, width - 1, "width {}", width);
+        }
+
+        let layout = layout(&items, 10, &options);
+        assert_eq!(spans(&layout), [(1, 1), (3, 2), (6, 3)]);
+    }
+}
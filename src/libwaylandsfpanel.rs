@@ -1,18 +1,63 @@
+mod child;
+mod logger;
+mod protocols;
+mod render;
+
+// only used by the bar, the menu builds the framework as well
+#[allow(dead_code)]
+pub mod icon;
+#[allow(dead_code)]
+mod keyboard;
+#[allow(dead_code)]
+mod lines;
+#[allow(dead_code)]
+mod toplevels;
+#[allow(dead_code)]
+mod uevent;
+
+pub use child::CommandOutput;
+pub use keyboard::KeyCombo;
+pub use logger::{enable_debug, increase_verbosity, quiet, set_target as set_log_target};
+pub use render::{Font, Image, Rect, Renderer};
+pub use toplevels::Toplevel;
+pub use uevent::Uevent;
+
+use render::AreaRenderer;
+pub use smithay_client_toolkit::reexports::calloop::timer::Timeout;
+
+use child::Children;
+use keyboard::VirtualKeyboard;
+use lines::LineReader;
+use protocols::virtual_keyboard::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1;
+use toplevels::Toplevels;
+
+use log::{debug, error, trace, warn};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use nix::sys::socket::{recv, MsgFlags};
+
 use smithay_client_toolkit::{
     default_environment,
     environment::SimpleGlobal,
     init_default_environment,
     output::{with_output_info, OutputInfo},
     reexports::{
-        calloop,
+        calloop::{
+            self,
+            generic::Generic,
+            signals::{Signal, Signals},
+            timer::TimerHandle,
+            Interest, LoopHandle, Mode,
+        },
         client::protocol::{
             wl_output,
             wl_pointer::{self, ButtonState},
             wl_shm, wl_surface, wl_touch,
         },
         client::{Attached, Main},
-        protocols::wlr::unstable::layer_shell::v1::client::{
-            zwlr_layer_shell_v1, zwlr_layer_surface_v1,
+        protocols::wlr::unstable::{
+            foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+            layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1},
         },
     },
     seat,
@@ -22,16 +67,26 @@ use smithay_client_toolkit::{
 
 use std::{
     cell::{Cell, RefCell},
+    collections::HashMap,
     io::{self, Seek, SeekFrom, Write},
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    process::{self, ChildStdin, Command},
     rc::Rc,
+    time::{Duration, Instant},
 };
 
 default_environment!(Env,
     fields = [
         layer_shell: SimpleGlobal<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
+        virtual_keyboard_manager: SimpleGlobal<ZwpVirtualKeyboardManagerV1>,
     ],
     singles = [
-        zwlr_layer_shell_v1::ZwlrLayerShellV1 => layer_shell
+        zwlr_layer_shell_v1::ZwlrLayerShellV1 => layer_shell,
+        ZwpVirtualKeyboardManagerV1 => virtual_keyboard_manager
     ],
 );
 
@@ -41,7 +96,9 @@ pub struct ApplicationSettings {
     pub layer: zwlr_layer_shell_v1::Layer,
     pub size: WindowSize,
     pub exclusive_zone: i32,
-    pub margins: (u32, u32, u32, u32),
+    /// Top, right, bottom and left, negative ones move the surface off the
+    /// output
+    pub margins: (i32, i32, i32, i32),
     pub anchor: zwlr_layer_surface_v1::Anchor,
 }
 
@@ -51,10 +108,454 @@ pub struct PointerPosition(pub f64, pub f64);
 #[derive(Clone, Copy)]
 pub struct WindowSize(pub u32, pub u32);
 
+/// Timeouts of the application and the framework share a single timer
+#[derive(Clone, Copy)]
+enum TimerEvent {
+    /// Goes to the surface which added it, or to every surface if it was
+    /// added outside of the calls into them
+    #[allow(dead_code)]
+    Application { id: u32, surface: Option<u32> },
+    /// Goes to every surface
+    #[allow(dead_code)]
+    Notify(u32),
+    /// Key of the periodic command to run
+    Periodic(usize),
+}
+
+/// A command started again `interval` after each time it exits
+struct Periodic {
+    id: u32,
+    name: String,
+    interval: Duration,
+    /// A command can only be detached once, so a new one is made for each run
+    command: Box<dyn Fn() -> Command>,
+}
+
+/// Passes a line printed by a command to every copy of the application
+type LineHandler = Rc<dyn Fn(u32, &str)>;
+/// Passes a device event to every copy of the application
+type UeventHandler = Rc<dyn Fn(&Uevent)>;
+
+/// Access to compositor features which are not tied to a surface, shared by
+/// every copy of the application
+#[derive(Clone)]
+pub struct Handle {
+    keyboard: Option<Rc<RefCell<VirtualKeyboard>>>,
+    children: Rc<RefCell<Children>>,
+    /// Removed once stopped, the timer refers to them by key
+    periodic: Rc<RefCell<HashMap<usize, Periodic>>>,
+    #[allow(dead_code)]
+    next_periodic: Rc<Cell<usize>>,
+    timer: TimerHandle<TimerEvent>,
+    #[allow(dead_code)]
+    event_loop: LoopHandle<()>,
+    #[allow(dead_code)]
+    deliver_line: LineHandler,
+    #[allow(dead_code)]
+    deliver_uevent: UeventHandler,
+    /// Whether the device events are received already
+    #[allow(dead_code)]
+    watching_uevents: Rc<Cell<bool>>,
+    toplevels: Rc<RefCell<Toplevels>>,
+    /// Whether the application has asked for the windows
+    watching_toplevels: Rc<Cell<bool>>,
+    /// The surface whose copy of the application is being called
+    current_surface: Rc<Cell<Option<u32>>>,
+}
+
+// the menu uses none of these
+#[allow(dead_code)]
+impl Handle {
+    /// Starts a command without waiting for it, see `Children::spawn`
+    pub fn spawn(&self, name: &str, command: &mut Command, log_output: bool) -> io::Result<()> {
+        self.children.borrow_mut().spawn(name, command, log_output)
+    }
+
+    /// Whether a command started with `name` is still running
+    pub fn is_running(&self, name: &str) -> bool {
+        self.children.borrow().is_running(name)
+    }
+
+    /// Calls `Application::timeout` with `id` once the delay has passed, on
+    /// the copy of the application which has added it. Timeouts added while
+    /// no copy is being called, e.g. from `Application::new`, go to every copy.
+    pub fn add_timeout(&self, delay: Duration, id: u32) -> Timeout {
+        self.timer.add_timeout(
+            delay,
+            TimerEvent::Application {
+                id,
+                surface: self.current_surface.get(),
+            },
+        )
+    }
+
+    /// Calls `Application::notify` with `id` on every copy of the
+    /// application, once the current call into it has returned
+    pub fn notify(&self, id: u32) {
+        self.timer
+            .add_timeout(Duration::from_millis(0), TimerEvent::Notify(id));
+    }
+
+    pub fn cancel_timeout(&self, timeout: &Timeout) {
+        self.timer.cancel_timeout(timeout);
+    }
+
+    /// Runs a command and passes its output to `Application::command_output`
+    /// of every copy of the application once it exits
+    pub fn run_for_output(&self, id: u32, name: &str, command: &mut Command) -> io::Result<()> {
+        self.children
+            .borrow_mut()
+            .spawn_with_output(name, command, id)
+    }
+
+    /// Runs a command right away and then `interval` after each time it
+    /// exits, its output is passed to `Application::command_output` of every
+    /// copy of the application each time
+    pub fn run_periodically<F>(&self, id: u32, name: &str, interval: Duration, command: F)
+    where
+        F: Fn() -> Command + 'static,
+    {
+        let key = self.next_periodic.get();
+        self.next_periodic.set(key + 1);
+
+        self.periodic.borrow_mut().insert(
+            key,
+            Periodic {
+                id,
+                name: name.to_owned(),
+                interval,
+                command: Box::new(command),
+            },
+        );
+
+        self.run_periodic(key);
+    }
+
+    fn run_periodic(&self, key: usize) {
+        let periodic = self.periodic.borrow();
+        let periodic = match periodic.get(&key) {
+            Some(periodic) => periodic,
+            None => return,
+        };
+
+        let result = self.children.borrow_mut().spawn_with_output(
+            &periodic.name,
+            &mut (periodic.command)(),
+            periodic.id,
+        );
+
+        // there won't be an exit to wait for
+        if let Err(e) = result {
+            error!("can't run '{}': {}", periodic.name, e);
+            self.timer
+                .add_timeout(periodic.interval, TimerEvent::Periodic(key));
+        }
+    }
+
+    /// Stops running the periodic commands, the output of every command which
+    /// is still running is dropped instead of being passed on
+    pub fn forget_commands(&self) {
+        self.periodic.borrow_mut().clear();
+
+        self.children.borrow_mut().forget_outputs();
+    }
+
+    /// Collects exited children and schedules the next run of the periodic
+    /// ones, returns their outputs
+    fn reap(&self) -> Vec<CommandOutput> {
+        let outputs = self.children.borrow_mut().reap();
+
+        for output in &outputs {
+            let periodic = self.periodic.borrow();
+            let found = periodic
+                .iter()
+                .find(|(_, periodic)| periodic.id == output.id);
+            if let Some((&key, periodic)) = found {
+                self.timer
+                    .add_timeout(periodic.interval, TimerEvent::Periodic(key));
+            }
+        }
+
+        outputs
+    }
+
+    /// Starts a command and passes each line it prints to
+    /// `Application::command_line` of every copy of the application together
+    /// with `id`. Writing to the returned stdin never blocks.
+    pub fn spawn_reader(
+        &self,
+        id: u32,
+        name: &str,
+        command: &mut Command,
+    ) -> io::Result<ChildStdin> {
+        let (stdin, stdout) = self.children.borrow_mut().spawn_piped(name, command)?;
+
+        let mut reader = LineReader::default();
+        let deliver_line = Rc::clone(&self.deliver_line);
+        let event_loop = self.event_loop.clone();
+        let name = name.to_owned();
+
+        // the source removes itself once the output ends
+        let source = Rc::new(Cell::new(None));
+        let source_handle = Rc::clone(&source);
+
+        let inserted = self
+            .event_loop
+            .insert_source(
+                Generic::new(stdout, Interest::Readable, Mode::Level),
+                move |_, stdout, _| {
+                    let (lines, ended) = reader.read(stdout);
+                    for line in &lines {
+                        deliver_line(id, line);
+                    }
+
+                    if ended {
+                        debug!("the output of '{}' has ended", name);
+                        if let Some(source) = source_handle.take() {
+                            event_loop.kill(source);
+                        }
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|e| e.error)?;
+        source.set(Some(inserted));
+
+        Ok(stdin)
+    }
+
+    /// Passes everything received from a socket to `on_data`, and an empty
+    /// slice once the connection is closed. The socket stays blocking for
+    /// writes through other handles.
+    pub fn read_socket<F>(&self, name: &str, socket: UnixStream, mut on_data: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) + 'static,
+    {
+        let event_loop = self.event_loop.clone();
+        let name = name.to_owned();
+
+        // the source removes itself once the connection is closed
+        let source = Rc::new(Cell::new(None));
+        let source_handle = Rc::clone(&source);
+
+        let inserted = self
+            .event_loop
+            .insert_source(
+                Generic::new(socket, Interest::Readable, Mode::Level),
+                move |_, socket, _| {
+                    let mut buffer = [0; 4096];
+                    let closed = loop {
+                        match recv(socket.as_raw_fd(), &mut buffer, MsgFlags::MSG_DONTWAIT) {
+                            Ok(0) => break true,
+                            Ok(n) => on_data(&buffer[..n]),
+                            Err(nix::Error::Sys(Errno::EINTR)) => {}
+                            Err(nix::Error::Sys(Errno::EAGAIN)) => break false,
+                            Err(e) => {
+                                debug!("can't read from {}: {}", name, e);
+                                break true;
+                            }
+                        }
+                    };
+
+                    if closed {
+                        debug!("the connection to {} has been closed", name);
+                        on_data(&[]);
+                        if let Some(source) = source_handle.take() {
+                            event_loop.kill(source);
+                        }
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|e| e.error)?;
+        source.set(Some(inserted));
+
+        Ok(())
+    }
+
+    /// Passes every connection made to a listening socket to `on_connection`
+    pub fn accept_connections<F>(
+        &self,
+        name: &str,
+        listener: UnixListener,
+        mut on_connection: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(UnixStream) + 'static,
+    {
+        listener.set_nonblocking(true)?;
+        let name = name.to_owned();
+
+        self.event_loop
+            .insert_source(
+                Generic::new(listener, Interest::Readable, Mode::Level),
+                move |_, listener, _| {
+                    loop {
+                        match listener.accept() {
+                            Ok((stream, _)) => on_connection(stream),
+                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(e) => {
+                                debug!("can't accept a connection to {}: {}", name, e);
+                                break;
+                            }
+                        }
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|e| e.error)?;
+
+        Ok(())
+    }
+
+    /// Calls `on_change` whenever a file has been written or replaced. The
+    /// directory is watched, as editors often save by renaming a new file.
+    pub fn watch_file<F>(&self, path: &Path, mut on_change: F) -> io::Result<()>
+    where
+        F: FnMut() + 'static,
+    {
+        let name = match path.file_name() {
+            Some(name) => name.to_owned(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a file")),
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let to_io_error = |e: nix::Error| io::Error::other(e.to_string());
+        let inotify =
+            Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC).map_err(to_io_error)?;
+        inotify
+            .add_watch(
+                dir,
+                AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO,
+            )
+            .map_err(to_io_error)?;
+
+        self.event_loop
+            .insert_source(
+                Generic::new(inotify, Interest::Readable, Mode::Level),
+                move |_, inotify, _| {
+                    let events = inotify.read_events().unwrap_or_default();
+                    if events
+                        .iter()
+                        .any(|event| event.name.as_ref() == Some(&name))
+                    {
+                        trace!("{:?} has changed", name);
+                        on_change();
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|e| e.error)?;
+
+        Ok(())
+    }
+
+    /// Passes the kernel device events to `Application::uevent` of every copy
+    /// of the application from now on
+    pub fn watch_uevents(&self) -> io::Result<()> {
+        if self.watching_uevents.get() {
+            return Ok(());
+        }
+
+        let socket = uevent::open()?;
+        let deliver_uevent = Rc::clone(&self.deliver_uevent);
+
+        self.event_loop
+            .insert_source(
+                Generic::new(socket, Interest::Readable, Mode::Level),
+                move |_, socket, _| {
+                    for event in uevent::read(socket) {
+                        trace!("uevent: {:?}", event);
+                        deliver_uevent(&event);
+                    }
+
+                    Ok(())
+                },
+            )
+            .map_err(|e| e.error)?;
+        self.watching_uevents.set(true);
+
+        Ok(())
+    }
+
+    /// Keeps track of the windows of every client once `Application::new`
+    /// has returned, if the compositor supports the foreign toplevel protocol.
+    /// `Application::toplevels_changed` of every copy of the application is
+    /// called whenever they change.
+    pub fn watch_toplevels(&self) {
+        self.watching_toplevels.set(true);
+    }
+
+    pub fn toplevels(&self) -> Vec<Toplevel> {
+        self.toplevels.borrow().list()
+    }
+
+    pub fn activate_toplevel(&self, id: u32) {
+        self.toplevels.borrow().activate(id);
+    }
+
+    /// Asks the client of the window to close it
+    pub fn close_toplevel(&self, id: u32) {
+        self.toplevels.borrow().close(id);
+    }
+
+    /// Presses and releases the key combinations one after another
+    pub fn press_keys(&self, combos: &[KeyCombo]) -> Result<(), String> {
+        match &self.keyboard {
+            Some(keyboard) => keyboard.borrow_mut().press(combos),
+            None => Err("the compositor does not support the virtual keyboard protocol".to_owned()),
+        }
+    }
+}
+
 pub trait Application: Sized + Clone {
-    fn new() -> Self;
+    fn new(handle: Handle) -> Self;
     fn settings(&self) -> ApplicationSettings;
-    fn draw(&mut self, size: WindowSize, buffer: &mut [u8]);
+    fn draw(&mut self, size: WindowSize, renderer: &mut dyn Renderer);
+
+    /// Whether every pixel drawn by `draw` has full alpha, which allows the
+    /// buffer to be submitted without an alpha channel
+    fn is_opaque(&self) -> bool {
+        false
+    }
+
+    /// A timeout added with `Handle::add_timeout` has expired
+    fn timeout(&mut self, _id: u32) -> Option<RenderEvent> {
+        None
+    }
+
+    /// A notification sent with `Handle::notify`
+    fn notify(&mut self, _id: u32) -> Option<RenderEvent> {
+        None
+    }
+
+    /// A command started with `Handle::run_for_output` or
+    /// `Handle::run_periodically` has exited
+    fn command_output(&mut self, _output: &CommandOutput) -> Option<RenderEvent> {
+        None
+    }
+
+    /// A command started with `Handle::spawn_reader` has printed a line
+    fn command_line(&mut self, _id: u32, _line: &str) -> Option<RenderEvent> {
+        None
+    }
+
+    /// A device event has arrived, see `Handle::watch_uevents`
+    fn uevent(&mut self, _event: &Uevent) -> Option<RenderEvent> {
+        None
+    }
+
+    /// The windows listed by `Handle::toplevels` have changed
+    fn toplevels_changed(&mut self) -> Option<RenderEvent> {
+        None
+    }
 
     fn input_start_gesture(&mut self, pos: PointerPosition) -> Option<RenderEvent>;
     fn input_stop_gesture(&mut self) -> Option<RenderEvent>;
@@ -65,25 +566,54 @@ pub trait Application: Sized + Clone {
 #[derive(PartialEq, Copy, Clone)]
 pub enum RenderEvent {
     Render,
-    Configure { width: u32, height: u32 },
+    /// Redraw only a part of the surface
+    #[allow(dead_code)]
+    RenderArea(Rect),
+    /// The compositor has sent a new size, which is kept apart so that no
+    /// other event replaces it
+    Configure,
+    /// Apply `Application::settings` again and redraw
+    Reconfigure,
     Closed,
 }
 
 struct Surface<T: Application> {
     app: T,
+    /// Id of the output, which also identifies the surface
+    id: u32,
+    /// Set to `id` while the application is called, see `Handle::add_timeout`
+    current_surface: Rc<Cell<Option<u32>>>,
     surface: wl_surface::WlSurface,
     layer_surface: Main<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
     next_render_event: Rc<Cell<Option<RenderEvent>>>,
+    /// Size of the last configure, until it's applied
+    next_dimensions: Rc<Cell<Option<WindowSize>>>,
     pools: DoubleMemPool,
     dimensions: WindowSize,
+    /// Pixels of the last frame, kept for partial redraws
+    frame: Vec<u8>,
     /// User requested exit
     should_exit: bool,
     last_pointer_location: Option<PointerPosition>,
 }
 
+fn apply_settings(
+    layer_surface: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    settings: &ApplicationSettings,
+) {
+    layer_surface.set_size(settings.size.0, settings.size.1);
+    layer_surface.set_exclusive_zone(settings.exclusive_zone);
+    layer_surface.set_anchor(settings.anchor);
+
+    let (top, right, bottom, left) = settings.margins;
+    layer_surface.set_margin(top, right, bottom, left);
+}
+
 impl<T: Application> Surface<T> {
     fn new(
         app: T,
+        id: u32,
+        current_surface: Rc<Cell<Option<u32>>>,
         output: &wl_output::WlOutput,
         surface: wl_surface::WlSurface,
         layer_shell: &Attached<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
@@ -93,20 +623,21 @@ impl<T: Application> Surface<T> {
 
         let layer_surface = layer_shell.get_layer_surface(
             &surface,
-            Some(&output),
+            Some(output),
             settings.layer,
             settings.namespace.to_owned(),
         );
 
-        layer_surface.set_size(settings.size.0, settings.size.1);
-        layer_surface.set_exclusive_zone(settings.exclusive_zone);
-        layer_surface.set_anchor(settings.anchor);
+        apply_settings(&layer_surface, &settings);
 
         let next_render_event = Rc::new(Cell::new(None::<RenderEvent>));
         let next_render_event_handle = Rc::clone(&next_render_event);
+        let next_dimensions = Rc::new(Cell::new(None));
+        let next_dimensions_handle = Rc::clone(&next_dimensions);
         layer_surface.quick_assign(move |layer_surface, event, _| {
             match (event, next_render_event_handle.get()) {
                 (zwlr_layer_surface_v1::Event::Closed, _) => {
+                    debug!("layer surface closed");
                     next_render_event_handle.set(Some(RenderEvent::Closed));
                 }
                 (
@@ -117,8 +648,14 @@ impl<T: Application> Surface<T> {
                     },
                     next,
                 ) if next != Some(RenderEvent::Closed) => {
+                    debug!("configure: serial {}, size {}x{}", serial, width, height);
                     layer_surface.ack_configure(serial);
-                    next_render_event_handle.set(Some(RenderEvent::Configure { width, height }));
+                    next_dimensions_handle.set(Some(WindowSize(width, height)));
+
+                    // redraws with the new size as well
+                    if next != Some(RenderEvent::Reconfigure) {
+                        next_render_event_handle.set(Some(RenderEvent::Configure));
+                    }
                 }
                 (_, _) => {}
             }
@@ -128,12 +665,16 @@ impl<T: Application> Surface<T> {
         surface.commit();
 
         Self {
-            app: app,
+            app,
+            id,
+            current_surface,
             surface,
             layer_surface,
             next_render_event,
+            next_dimensions,
             pools,
             dimensions: WindowSize(0, 0),
+            frame: vec![],
             should_exit: false,
             last_pointer_location: None,
         }
@@ -142,15 +683,40 @@ impl<T: Application> Surface<T> {
     /// Handles any events that have occurred since the last call, redrawing if needed.
     /// Returns true if the surface should be dropped.
     fn handle_events(&mut self) -> bool {
+        if let Some(dimensions) = self.next_dimensions.take() {
+            self.dimensions = dimensions;
+        }
+
+        // nothing can be attached before the first configure, so anything
+        // else waits for it
+        let configured = self.dimensions.0 != 0 && self.dimensions.1 != 0;
+
         match self.next_render_event.take() {
+            Some(event @ RenderEvent::Render)
+            | Some(event @ RenderEvent::RenderArea(_))
+            | Some(event @ RenderEvent::Reconfigure)
+                if !configured =>
+            {
+                self.next_render_event.set(Some(event));
+                self.should_exit
+            }
             Some(RenderEvent::Render) => {
-                self.draw();
+                self.draw(None);
+                false
+            }
+            Some(RenderEvent::RenderArea(area)) => {
+                self.draw(Some(area));
                 false
             }
             Some(RenderEvent::Closed) => true,
-            Some(RenderEvent::Configure { width, height }) => {
-                self.dimensions = WindowSize(width, height);
-                self.draw();
+            Some(RenderEvent::Reconfigure) => {
+                // committed together with the next frame
+                apply_settings(&self.layer_surface, &self.app.settings());
+                self.draw(None);
+                false
+            }
+            Some(RenderEvent::Configure) => {
+                self.draw(None);
                 false
             }
             None => self.should_exit,
@@ -158,32 +724,82 @@ impl<T: Application> Surface<T> {
     }
 
     fn update_event(&mut self, result: Option<RenderEvent>) {
-        if let Some(..) = result {
-            self.next_render_event.set(result);
-        }
+        let next = match (self.next_render_event.get(), result) {
+            (_, None) => return,
+            (Some(RenderEvent::RenderArea(pending)), Some(RenderEvent::RenderArea(area))) => {
+                RenderEvent::RenderArea(pending.union(&area))
+            }
+            // anything else redraws the whole surface already
+            (Some(pending), Some(RenderEvent::RenderArea(_))) => pending,
+            (Some(RenderEvent::Reconfigure), Some(RenderEvent::Render)) => RenderEvent::Reconfigure,
+            (_, Some(event)) => event,
+        };
+
+        self.next_render_event.set(Some(next));
+    }
+
+    /// Calls the application, the timeouts it adds are its own
+    fn call<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        self.current_surface.set(Some(self.id));
+        let result = f(&mut self.app);
+        self.current_surface.set(None);
+
+        result
+    }
+
+    fn timeout(&mut self, id: u32) {
+        let result = self.call(|app| app.timeout(id));
+        self.update_event(result);
+    }
+
+    fn notify(&mut self, id: u32) {
+        let result = self.call(|app| app.notify(id));
+        self.update_event(result);
+    }
+
+    fn command_output(&mut self, output: &CommandOutput) {
+        let result = self.call(|app| app.command_output(output));
+        self.update_event(result);
+    }
+
+    fn command_line(&mut self, id: u32, line: &str) {
+        let result = self.call(|app| app.command_line(id, line));
+        self.update_event(result);
+    }
+
+    fn uevent(&mut self, event: &Uevent) {
+        let result = self.call(|app| app.uevent(event));
+        self.update_event(result);
+    }
+
+    fn toplevels_changed(&mut self) {
+        let result = self.call(|app| app.toplevels_changed());
+        self.update_event(result);
     }
 
     fn input_stop_gesture(&mut self) {
-        let result = self.app.input_stop_gesture();
+        let result = self.call(|app| app.input_stop_gesture());
         self.update_event(result);
     }
 
     fn input_start_gesture(&mut self, pos: PointerPosition) {
-        let result = self.app.input_start_gesture(pos);
+        let result = self.call(|app| app.input_start_gesture(pos));
         self.update_event(result);
     }
 
     fn input_movement(&mut self, pos: PointerPosition) {
-        let result = self.app.input_movement(pos);
+        let result = self.call(|app| app.input_movement(pos));
         self.update_event(result);
     }
 
     fn input_commit_gesture(&mut self) {
-        let result = self.app.input_commit_gesture();
+        let result = self.call(|app| app.input_commit_gesture());
         self.update_event(result);
     }
 
     fn handle_touch_event(&mut self, event: &wl_touch::Event) {
+        trace!("touch event: {:?}", event);
+
         match event {
             wl_touch::Event::Cancel => self.input_stop_gesture(),
             wl_touch::Event::Down { x, y, .. } => self.input_start_gesture(PointerPosition(*x, *y)),
@@ -194,6 +810,8 @@ impl<T: Application> Surface<T> {
     }
 
     fn handle_pointer_event(&mut self, event: &wl_pointer::Event) {
+        trace!("pointer event: {:?}", event);
+
         match event {
             wl_pointer::Event::Leave { .. } => {
                 self.input_stop_gesture();
@@ -230,37 +848,107 @@ impl<T: Application> Surface<T> {
         }
     }
 
-    fn draw(&mut self) {
+    /// Draws the whole surface, or only an area of it on top of the last frame
+    fn draw(&mut self, area: Option<Rect>) {
         let pool = match self.pools.pool() {
             Some(pool) => pool,
-            None => return,
+            None => {
+                debug!("draw skipped: no free buffer");
+                return;
+            }
         };
 
+        let started = Instant::now();
+
         let stride = 4 * self.dimensions.0 as i32;
         let width = self.dimensions.0 as i32;
         let height = self.dimensions.1 as i32;
 
         // First make sure the pool is the right size
-        pool.resize((stride * height) as usize).unwrap();
+        if let Err(e) = pool.resize((stride * height) as usize) {
+            error!("can't resize memory pool: {}", e);
+            return;
+        }
+
+        let bounds = Rect::new(0, 0, width as u32, height as u32);
+        let area = match area.and_then(|area| area.intersect(&bounds)) {
+            Some(area) if self.frame.len() == (4 * width * height) as usize => Some(area),
+            _ => None,
+        };
 
-        let mut buf: Vec<u8> = vec![0; (4 * width * height) as usize];
+        let mut buf: Vec<u8> = match area {
+            Some(area) => {
+                let mut buf = std::mem::take(&mut self.frame);
+                for y in area.y..area.bottom() {
+                    let start = (y * stride + 4 * area.x) as usize;
+                    buf[start..start + 4 * area.width as usize].fill(0);
+                }
+                buf
+            }
+            None => vec![0; (4 * width * height) as usize],
+        };
 
-        self.app.draw(self.dimensions, &mut buf);
+        {
+            // like `call`, which can't be used while the pool is borrowed
+            self.current_surface.set(Some(self.id));
 
-        pool.seek(SeekFrom::Start(0)).unwrap();
-        pool.write_all(buf.as_slice()).unwrap();
-        pool.flush().unwrap();
+            let renderer = render::new_renderer(&mut buf, self.dimensions);
+            match area {
+                Some(area) => {
+                    let mut renderer = AreaRenderer::new(renderer, area);
+                    self.app.draw(self.dimensions, &mut renderer);
+                }
+                None => {
+                    let mut renderer = renderer;
+                    self.app.draw(self.dimensions, &mut renderer);
+                }
+            }
+
+            self.current_surface.set(None);
+        }
+
+        if let Err(e) = pool
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| pool.write_all(buf.as_slice()))
+            .and_then(|_| pool.flush())
+        {
+            error!("can't write to memory pool: {}", e);
+            return;
+        }
+
+        // Drop the alpha channel if it is not needed, so that the compositor
+        // does not have to blend the surface
+        let format = match self.app.is_opaque() {
+            true => wl_shm::Format::Xrgb8888,
+            false => wl_shm::Format::Argb8888,
+        };
 
         // Create a new buffer from the pool
-        let buffer = pool.buffer(0, width, height, stride, wl_shm::Format::Argb8888);
+        let buffer = pool.buffer(0, width, height, stride, format);
 
-        // Attach the buffer to the surface and mark the entire surface as damaged
+        // Attach the buffer to the surface and mark the redrawn part as damaged
+        let damage = area.unwrap_or(bounds);
         self.surface.attach(Some(&buffer), 0, 0);
-        self.surface
-            .damage_buffer(0, 0, width as i32, height as i32);
+        self.surface.damage_buffer(
+            damage.x,
+            damage.y,
+            damage.width as i32,
+            damage.height as i32,
+        );
 
         // Finally, commit the surface
         self.surface.commit();
+
+        self.frame = buf;
+
+        debug!(
+            "draw: {}x{} {:?} ({:?}) took {:?}",
+            width,
+            height,
+            format,
+            damage,
+            started.elapsed()
+        );
     }
 }
 
@@ -275,33 +963,220 @@ pub fn run_application<A>()
 where
     A: Application + 'static,
 {
-    let (env, display, queue) =
-        init_default_environment!(Env, fields = [layer_shell: SimpleGlobal::new(),])
-            .expect("Initial roundtrip failed!");
+    logger::init();
+
+    let mut event_loop = match calloop::EventLoop::<()>::new() {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            error!("can't create the event loop: {}", e);
+
+            process::exit(1);
+        }
+    };
+
+    let surfaces = Rc::new(RefCell::new(Vec::<(u32, Surface<A>)>::new()));
+
+    // SIGCHLD gets blocked for this thread only, so this has to happen
+    // before the timer spawns its thread
+    let sigchld = match Signals::new(&[Signal::SIGCHLD]) {
+        Ok(sigchld) => sigchld,
+        Err(e) => {
+            error!("can't listen to SIGCHLD: {}", e);
+
+            process::exit(1);
+        }
+    };
+
+    let timer = match calloop::timer::Timer::new() {
+        Ok(timer) => timer,
+        Err(e) => {
+            error!("can't create a timer: {}", e);
+
+            process::exit(1);
+        }
+    };
+
+    let surfaces_handle = Rc::clone(&surfaces);
+    let deliver_line = move |id, line: &str| {
+        for surface in surfaces_handle.borrow_mut().iter_mut() {
+            surface.1.command_line(id, line);
+        }
+    };
+
+    let surfaces_handle = Rc::clone(&surfaces);
+    let deliver_uevent = move |event: &Uevent| {
+        for surface in surfaces_handle.borrow_mut().iter_mut() {
+            surface.1.uevent(event);
+        }
+    };
+
+    // everything but the keyboard, which needs a connection to the compositor
+    let mut handle = Handle {
+        keyboard: None,
+        children: Rc::new(RefCell::new(Children::default())),
+        periodic: Rc::new(RefCell::new(HashMap::new())),
+        next_periodic: Rc::new(Cell::new(0)),
+        timer: timer.handle(),
+        event_loop: event_loop.handle(),
+        deliver_line: Rc::new(deliver_line),
+        deliver_uevent: Rc::new(deliver_uevent),
+        watching_uevents: Rc::new(Cell::new(false)),
+        toplevels: Rc::new(RefCell::new(Toplevels::default())),
+        watching_toplevels: Rc::new(Cell::new(false)),
+        current_surface: Rc::new(Cell::new(None)),
+    };
+
+    let handle_copy = handle.clone();
+    let surfaces_handle = Rc::clone(&surfaces);
+    if let Err(e) = event_loop.handle().insert_source(sigchld, move |_, _, _| {
+        for output in handle_copy.reap() {
+            for surface in surfaces_handle.borrow_mut().iter_mut() {
+                surface.1.command_output(&output);
+            }
+        }
+    }) {
+        error!("can't listen to SIGCHLD: {}", e.error);
+
+        process::exit(1);
+    }
+
+    let handle_copy = handle.clone();
+    let surfaces_handle = Rc::clone(&surfaces);
+    if let Err(e) = event_loop
+        .handle()
+        .insert_source(timer, move |event, _, _| match event {
+            TimerEvent::Application { id, surface: None } => {
+                for surface in surfaces_handle.borrow_mut().iter_mut() {
+                    surface.1.timeout(id);
+                }
+            }
+            TimerEvent::Application {
+                id,
+                surface: Some(owner),
+            } => {
+                // gone with its output otherwise
+                if let Some(surface) = surfaces_handle
+                    .borrow_mut()
+                    .iter_mut()
+                    .find(|(i, _)| *i == owner)
+                {
+                    surface.1.timeout(id);
+                }
+            }
+            TimerEvent::Notify(id) => {
+                for surface in surfaces_handle.borrow_mut().iter_mut() {
+                    surface.1.notify(id);
+                }
+            }
+            TimerEvent::Periodic(key) => handle_copy.run_periodic(key),
+        })
+    {
+        error!("can't listen to timers: {}", e.error);
+
+        process::exit(1);
+    }
+
+    let (env, display, queue) = match init_default_environment!(
+        Env,
+        fields = [
+            layer_shell: SimpleGlobal::new(),
+            virtual_keyboard_manager: SimpleGlobal::new(),
+        ]
+    ) {
+        Ok(environment) => environment,
+        Err(e) => {
+            error!("can't connect to the wayland compositor: {}", e);
+
+            process::exit(1);
+        }
+    };
 
-    let surfaces = Rc::new(RefCell::new(Vec::new()));
+    let layer_shell = match env.get_global::<zwlr_layer_shell_v1::ZwlrLayerShellV1>() {
+        Some(layer_shell) => layer_shell,
+        None => {
+            error!("the compositor does not support the layer shell protocol");
+
+            process::exit(1);
+        }
+    };
 
-    let layer_shell = env.require_global::<zwlr_layer_shell_v1::ZwlrLayerShellV1>();
+    // key presses are sent from the first seat, which is the only one on a phone
+    handle.keyboard = match (
+        env.get_global::<ZwpVirtualKeyboardManagerV1>(),
+        env.get_all_seats().first(),
+    ) {
+        (Some(manager), Some(seat)) => {
+            Some(Rc::new(RefCell::new(VirtualKeyboard::new(&manager, seat))))
+        }
+        (None, _) => {
+            debug!("the compositor does not support the virtual keyboard protocol");
+            None
+        }
+        (_, None) => None,
+    };
+
+    let toplevels = Rc::clone(&handle.toplevels);
+    let current_surface = Rc::clone(&handle.current_surface);
+    let watching_toplevels = Rc::clone(&handle.watching_toplevels);
 
     let env_handle = env.clone();
     let surfaces_handle = Rc::clone(&surfaces);
-    let template = A::new();
+    let template = A::new(handle);
+
+    // the windows are only tracked if the application has asked for them
+    if watching_toplevels.get() {
+        let surfaces_handle = Rc::clone(&surfaces);
+        let deliver_toplevels = move || {
+            for surface in surfaces_handle.borrow_mut().iter_mut() {
+                surface.1.toplevels_changed();
+            }
+        };
+
+        match (
+            env.manager
+                .instantiate_range::<ZwlrForeignToplevelManagerV1>(1, 2),
+            env.get_all_seats().first(),
+        ) {
+            (Ok(manager), Some(seat)) => {
+                Toplevels::listen(&toplevels, &manager, seat, Rc::new(deliver_toplevels))
+            }
+            (Err(..), _) => warn!("the compositor does not support the foreign toplevel protocol"),
+            (_, None) => {}
+        }
+    }
 
     let output_handler = move |output: wl_output::WlOutput, info: &OutputInfo| {
+        debug!(
+            "output {} ({} {}): scale {}, obsolete {}",
+            info.id, info.make, info.model, info.scale_factor, info.obsolete
+        );
+
         if info.obsolete {
             // an output has been removed, release it
             surfaces_handle.borrow_mut().retain(|(i, _)| *i != info.id);
             output.release();
         } else {
             // an output has been created, construct a surface for it
+            let pools = match env_handle.create_double_pool(|_| {}) {
+                Ok(pools) => pools,
+                Err(e) => {
+                    error!("can't create a memory pool for output {}: {}", info.id, e);
+                    return;
+                }
+            };
             let surface = env_handle.create_surface().detach();
-            let pools = env_handle
-                .create_double_pool(|_| {})
-                .expect("Failed to create a memory pool!");
             let app = template.clone();
             (*surfaces_handle.borrow_mut()).push((
                 info.id,
-                Surface::new(app, &output, surface, &layer_shell.clone(), pools),
+                Surface::new(
+                    app,
+                    info.id,
+                    Rc::clone(&current_surface),
+                    &output,
+                    surface,
+                    &layer_shell.clone(),
+                    pools,
+                ),
             ));
         }
     };
@@ -352,11 +1227,11 @@ where
     let _listner_handle =
         env.listen_for_outputs(move |output, info, _| output_handler(output, info));
 
-    let mut event_loop = calloop::EventLoop::<()>::new().unwrap();
+    if let Err(e) = WaylandSource::new(queue).quick_insert(event_loop.handle()) {
+        error!("can't listen to wayland events: {}", e);
 
-    WaylandSource::new(queue)
-        .quick_insert(event_loop.handle())
-        .unwrap();
+        process::exit(1);
+    }
 
     loop {
         // This is ugly, let's hope that some version of drain_filter() gets stabilized soon
@@ -379,14 +1254,20 @@ where
             return;
         }
 
-        display.flush().unwrap();
+        if let Err(e) = display.flush() {
+            error!("lost connection to the compositor: {}", e);
+
+            process::exit(1);
+        }
 
         match event_loop.dispatch(None, &mut ()) {
             Ok(..) => {}
             Err(err) => {
                 // err interrupted somehow happens after suspend :/
                 if err.kind() != io::ErrorKind::Interrupted {
-                    panic!("Unexpected dispatch event: {:?}", err);
+                    error!("unexpected dispatch error: {:?}", err);
+
+                    process::exit(1);
                 }
             }
         }
@@ -0,0 +1,284 @@
+use log::{debug, info, warn};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
+    sys::{
+        memfd::{memfd_create, MemFdCreateFlag},
+        signal::{sigprocmask, SigSet, SigmaskHow},
+    },
+    unistd::setsid,
+};
+
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::CommandExt,
+    },
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// Checked for leaked file descriptors if `/proc` is not available
+const MAX_FD: RawFd = 1024;
+
+struct RunningChild {
+    child: Child,
+    name: String,
+    /// Receives stderr of the child if its output should be logged
+    stderr: Option<File>,
+    /// Receives stdout of the child if it is passed on, with its id
+    stdout: Option<(u32, File)>,
+}
+
+/// Result of a command started with `Children::spawn_with_output`
+#[allow(dead_code)]
+pub struct CommandOutput {
+    pub id: u32,
+    /// Whether the command exited with status 0
+    pub success: bool,
+    pub stdout: String,
+}
+
+impl CommandOutput {
+    fn failed(id: u32) -> Self {
+        CommandOutput {
+            id,
+            success: false,
+            stdout: String::new(),
+        }
+    }
+}
+
+/// Commands launched by the application which have not exited yet.
+///
+/// Nothing blocks on a child; they are reaped whenever `SIGCHLD` arrives.
+/// Children are detached from the application: they run in a new session
+/// with an empty signal mask and don't inherit any file descriptors except
+/// for stdout and stderr.
+#[derive(Default)]
+pub struct Children {
+    running: Vec<RunningChild>,
+}
+
+impl Children {
+    /// Starts a command, `name` identifies it in the log and for `is_running`.
+    ///
+    /// With `log_output`, the exit status and everything the child writes to
+    /// stderr end up in the log once it exits.
+    pub fn spawn(&mut self, name: &str, command: &mut Command, log_output: bool) -> io::Result<()> {
+        // stderr goes to an in-memory file instead of a pipe, so a chatty
+        // child can never block on it
+        let stderr = match log_output {
+            true => Some(output_file()?),
+            false => None,
+        };
+
+        if let Some(stderr) = &stderr {
+            command.stderr(Stdio::from(stderr.try_clone()?));
+        }
+
+        command.stdin(Stdio::null());
+        self.start(name, command, stderr, None)?;
+
+        Ok(())
+    }
+
+    /// Starts a command whose stdout is returned by `reap` together with
+    /// `id` once it exits
+    pub fn spawn_with_output(
+        &mut self,
+        name: &str,
+        command: &mut Command,
+        id: u32,
+    ) -> io::Result<()> {
+        let stdout = output_file()?;
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(stdout.try_clone()?));
+
+        self.start(name, command, None, Some((id, stdout)))?;
+
+        Ok(())
+    }
+
+    /// Starts a command with non-blocking pipes to its stdin and from its stdout
+    pub fn spawn_piped(
+        &mut self,
+        name: &str,
+        command: &mut Command,
+    ) -> io::Result<(ChildStdin, ChildStdout)> {
+        command.stdin(Stdio::piped()).stdout(Stdio::piped());
+
+        let child = self.start(name, command, None, None)?;
+        match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => {
+                set_nonblocking(stdin.as_raw_fd())?;
+                set_nonblocking(stdout.as_raw_fd())?;
+
+                Ok((stdin, stdout))
+            }
+            // both are piped
+            _ => Err(io::Error::other("missing pipes")),
+        }
+    }
+
+    fn start(
+        &mut self,
+        name: &str,
+        command: &mut Command,
+        stderr: Option<File>,
+        stdout: Option<(u32, File)>,
+    ) -> io::Result<&mut Child> {
+        detach(command);
+
+        let child = command.spawn()?;
+        debug!("started '{}' as {}", name, child.id());
+
+        self.running.push(RunningChild {
+            child,
+            name: name.to_owned(),
+            stderr,
+            stdout,
+        });
+
+        // just pushed
+        Ok(&mut self.running.last_mut().unwrap().child)
+    }
+
+    /// Drops the stdout of the running children, `reap` won't return their
+    /// outputs
+    pub fn forget_outputs(&mut self) {
+        for child in &mut self.running {
+            child.stdout = None;
+        }
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running.iter().any(|child| child.name == name)
+    }
+
+    /// Collects every child that has exited, returns the outputs of those
+    /// started with `spawn_with_output`. A child whose status or output
+    /// can't be read counts as failed with an empty output.
+    pub fn reap(&mut self) -> Vec<CommandOutput> {
+        let mut outputs = vec![];
+
+        let mut i = 0;
+        while i != self.running.len() {
+            match self.running[i].child.try_wait() {
+                Ok(None) => i += 1,
+                Ok(Some(status)) => {
+                    let mut child = self.running.remove(i);
+
+                    match &mut child.stderr {
+                        Some(stderr) => {
+                            if status.success() {
+                                info!("'{}' exited", child.name);
+                            } else {
+                                warn!("'{}' failed: {}", child.name, status);
+                            }
+
+                            log_output(&child.name, stderr);
+                        }
+                        None => debug!("'{}' exited: {}", child.name, status),
+                    }
+
+                    if let Some((id, stdout)) = &mut child.stdout {
+                        let output = match read_output(stdout) {
+                            Ok(stdout) => CommandOutput {
+                                id: *id,
+                                success: status.success(),
+                                stdout,
+                            },
+                            Err(e) => {
+                                warn!("can't read the output of '{}': {}", child.name, e);
+                                CommandOutput::failed(*id)
+                            }
+                        };
+                        outputs.push(output);
+                    }
+                }
+                Err(e) => {
+                    let child = self.running.remove(i);
+                    warn!("can't get the status of '{}': {}", child.name, e);
+
+                    if let Some((id, _)) = child.stdout {
+                        outputs.push(CommandOutput::failed(id));
+                    }
+                }
+            }
+        }
+
+        outputs
+    }
+}
+
+fn detach(command: &mut Command) {
+    // listed before forking, as reading a directory allocates
+    let fds: Vec<RawFd> = match fs::read_dir("/proc/self/fd") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter(|fd| *fd > 2)
+            .collect(),
+        Err(..) => (3..MAX_FD).collect(),
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            setsid().map_err(to_io_error)?;
+            sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None)
+                .map_err(to_io_error)?;
+
+            // closing the descriptors right away would also close the pipe
+            // used to report a failing exec, so they are closed by it instead
+            for fd in &fds {
+                let _ = fcntl(*fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC));
+            }
+
+            Ok(())
+        });
+    }
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    fcntl(fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(to_io_error)?;
+
+    Ok(())
+}
+
+fn to_io_error(e: nix::Error) -> io::Error {
+    match e {
+        nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::other(e),
+    }
+}
+
+fn output_file() -> io::Result<File> {
+    let name = CString::new("ppkui-output").unwrap_or_default();
+    let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC).map_err(to_io_error)?;
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn read_output(file: &mut File) -> io::Result<String> {
+    let mut output = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut output)?;
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+fn log_output(name: &str, stderr: &mut File) {
+    let output = match read_output(stderr) {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("can't read the output of '{}': {}", name, e);
+            return;
+        }
+    };
+
+    for line in output.lines() {
+        info!("{}: {}", name, line);
+    }
+}
@@ -0,0 +1,179 @@
+use super::Image;
+
+use log::debug;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, TreeParsing},
+};
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Theme every other icon theme falls back to
+const FALLBACK_THEME: &str = "hicolor";
+
+/// Loads an icon as a square image of `size` pixels.
+///
+/// `icon` is either a path to a png or svg file, or the name of an icon
+/// looked up in `theme` (e.g. `go-previous-symbolic`).
+pub fn load_icon(icon: &str, theme: &str, size: u32) -> Result<Image, String> {
+    let is_file = icon.contains('/')
+        || [".png", ".svg", ".svgz"]
+            .iter()
+            .any(|extension| icon.ends_with(extension));
+
+    let path = match is_file {
+        true => PathBuf::from(icon),
+        false => find_icon(icon, theme)
+            .ok_or_else(|| format!("can't find icon '{}' in theme '{}'", icon, theme))?,
+    };
+
+    debug!("loading icon {:?}", path);
+
+    let data = fs::read(&path).map_err(|e| format!("can't read icon {:?}: {}", path, e))?;
+
+    let image = match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") | Some("svgz") => load_svg(&data, size),
+        Some("png") => load_png(&data),
+        _ => Err("only png and svg icons are supported".to_owned()),
+    };
+
+    image.map_err(|e| format!("can't load icon {:?}: {}", path, e))
+}
+
+/// Renders an svg scaled to fit the square, keeping its aspect ratio
+fn load_svg(data: &[u8], size: u32) -> Result<Image, String> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|e| e.to_string())?;
+    let tree = resvg::Tree::from_usvg(&tree);
+
+    let mut pixmap = Pixmap::new(size, size).ok_or("invalid icon size")?;
+
+    let (width, height) = (tree.size.width(), tree.size.height());
+    let scale = size as f32 / width.max(height);
+    let transform = Transform::from_scale(scale, scale).post_translate(
+        (size as f32 - width * scale) / 2.,
+        (size as f32 - height * scale) / 2.,
+    );
+
+    tree.render(transform, &mut pixmap.as_mut());
+
+    // premultiplied RGBA
+    let pixels: Vec<[u8; 4]> = pixmap
+        .data()
+        .chunks_exact(4)
+        .map(|p| [p[3], p[0], p[1], p[2]])
+        .collect();
+
+    Image::from_argb(size, size, &pixels).ok_or_else(|| "invalid svg".to_owned())
+}
+
+/// Decodes a png at its own size, it gets scaled when drawn
+fn load_png(data: &[u8]) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    let premultiply = |r: u8, g: u8, b: u8, a: u8| {
+        let scale = |c: u8| (f32::from(c) * f32::from(a) / 255.).round() as u8;
+        [a, scale(r), scale(g), scale(b)]
+    };
+
+    let pixels: Vec<[u8; 4]> = match info.color_type {
+        png::ColorType::Rgba => buffer
+            .chunks_exact(4)
+            .map(|p| premultiply(p[0], p[1], p[2], p[3]))
+            .collect(),
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .map(|p| [255, p[0], p[1], p[2]])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .map(|p| premultiply(p[0], p[0], p[0], p[1]))
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().map(|g| [255, *g, *g, *g]).collect(),
+        // expanded by the decoder
+        png::ColorType::Indexed => return Err("unexpected indexed colors".to_owned()),
+    };
+
+    Image::from_argb(info.width, info.height, &pixels).ok_or_else(|| "invalid png".to_owned())
+}
+
+/// Looks an icon up in the icon theme directories, preferring scalable icons
+/// over the largest bitmap one
+fn find_icon(name: &str, theme: &str) -> Option<PathBuf> {
+    let data_home = env::var("XDG_DATA_HOME").ok().or_else(|| {
+        env::var("HOME")
+            .ok()
+            .map(|home| format!("{}/.local/share", home))
+    });
+    let data_dirs =
+        env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".to_owned());
+
+    let data_dirs: Vec<String> = data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(str::to_owned))
+        .collect();
+
+    for theme in &[theme, FALLBACK_THEME] {
+        let mut candidates = vec![];
+        for dir in &data_dirs {
+            find_files(
+                &Path::new(dir).join("icons").join(theme),
+                name,
+                &mut candidates,
+            );
+        }
+
+        let best = candidates.into_iter().max_by_key(|path| icon_quality(path));
+        if best.is_some() {
+            return best;
+        }
+    }
+
+    ["svg", "png"]
+        .iter()
+        .map(|extension| Path::new("/usr/share/pixmaps").join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+fn find_files(dir: &Path, name: &str, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(..) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if path.is_dir() {
+            find_files(&path, name, found);
+        } else if path.file_stem().and_then(|s| s.to_str()) == Some(name)
+            && matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("svg") | Some("png")
+            )
+        {
+            found.push(path);
+        }
+    }
+}
+
+/// Scalable icons come first, bitmaps are ranked by the size in their
+/// directory name, like `48x48`
+fn icon_quality(path: &Path) -> u32 {
+    if path.extension().and_then(|e| e.to_str()) == Some("svg") {
+        return u32::MAX;
+    }
+
+    path.components()
+        .filter_map(|c| c.as_os_str().to_str()?.split('x').next()?.parse().ok())
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,273 @@
+use super::protocols::virtual_keyboard::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+
+use log::debug;
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use smithay_client_toolkit::reexports::client::{
+    protocol::{wl_keyboard, wl_seat},
+    Attached, Main,
+};
+
+use std::{
+    ffi::CString,
+    fmt,
+    fs::File,
+    io::Write,
+    os::unix::io::{AsRawFd, FromRawFd},
+    str::FromStr,
+    time::Instant,
+};
+
+// Real modifier masks, these are fixed by the "complete" xkb types
+const MOD_SHIFT: u32 = 1;
+const MOD_CONTROL: u32 = 1 << 2;
+const MOD_ALT: u32 = 1 << 3;
+const MOD_SUPER: u32 = 1 << 6;
+
+/// A key pressed together with modifiers, written like `ctrl+shift+t`.
+///
+/// The key is an xkb keysym name, such as `Escape`, `w` or
+/// `XF86AudioRaiseVolume`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyCombo {
+    modifiers: u32,
+    keysym: String,
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+
+        // split always returns at least one element
+        let keysym = parts.pop().unwrap_or_default();
+
+        let mut modifiers = 0;
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "shift" => MOD_SHIFT,
+                "ctrl" | "control" => MOD_CONTROL,
+                "alt" => MOD_ALT,
+                "super" | "logo" | "win" => MOD_SUPER,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", part, s)),
+            };
+        }
+
+        // keysyms end up in the keymap source, don't let anything else in
+        if keysym.is_empty()
+            || !keysym
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("invalid key '{}' in '{}'", keysym, s));
+        }
+
+        Ok(KeyCombo {
+            modifiers,
+            keysym: keysym.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (mask, name) in &[
+            (MOD_CONTROL, "ctrl"),
+            (MOD_SHIFT, "shift"),
+            (MOD_ALT, "alt"),
+            (MOD_SUPER, "super"),
+        ] {
+            if self.modifiers & mask != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        write!(f, "{}", self.keysym)
+    }
+}
+
+/// Emulates key presses with `zwp_virtual_keyboard_v1`.
+///
+/// Instead of relying on the user's layout, the keyboard uploads its own
+/// keymap which has exactly one key per keysym used so far; the keymap is
+/// extended and uploaded again whenever a new keysym shows up.
+pub struct VirtualKeyboard {
+    keyboard: Main<ZwpVirtualKeyboardV1>,
+    /// Keysyms present in the keymap, the key code of each is its index + 1
+    keysyms: Vec<String>,
+    created: Instant,
+}
+
+impl VirtualKeyboard {
+    pub fn new(manager: &Attached<ZwpVirtualKeyboardManagerV1>, seat: &wl_seat::WlSeat) -> Self {
+        VirtualKeyboard {
+            keyboard: manager.create_virtual_keyboard(seat),
+            keysyms: vec![],
+            created: Instant::now(),
+        }
+    }
+
+    /// Presses and releases every combo in order
+    pub fn press(&mut self, combos: &[KeyCombo]) -> Result<(), String> {
+        let mut keymap_changed = false;
+        for combo in combos {
+            if !self.keysyms.contains(&combo.keysym) {
+                self.keysyms.push(combo.keysym.clone());
+                keymap_changed = true;
+            }
+        }
+
+        if keymap_changed {
+            self.upload_keymap()?;
+        }
+
+        let time = self.created.elapsed().as_millis() as u32;
+
+        for combo in combos {
+            debug!("pressing {}", combo);
+
+            let key = match self.keysyms.iter().position(|k| *k == combo.keysym) {
+                Some(index) => index as u32 + 1,
+                None => continue,
+            };
+
+            if combo.modifiers != 0 {
+                self.keyboard.modifiers(combo.modifiers, 0, 0, 0);
+            }
+
+            self.keyboard
+                .key(time, key, wl_keyboard::KeyState::Pressed.to_raw());
+            self.keyboard
+                .key(time, key, wl_keyboard::KeyState::Released.to_raw());
+
+            if combo.modifiers != 0 {
+                self.keyboard.modifiers(0, 0, 0, 0);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn keymap(&self) -> String {
+        let mut keycodes = String::new();
+        let mut symbols = String::new();
+
+        for (index, keysym) in self.keysyms.iter().enumerate() {
+            // xkb key codes are offset by 8 from the evdev ones used by the protocol
+            keycodes.push_str(&format!("<K{}> = {};\n", index + 1, index + 9));
+            symbols.push_str(&format!("key <K{}> {{ [ {} ] }};\n", index + 1, keysym));
+        }
+
+        format!(
+            "xkb_keymap {{\n\
+             xkb_keycodes \"ppkui\" {{\n\
+             minimum = 8;\n\
+             maximum = {};\n\
+             {}}};\n\
+             xkb_types \"ppkui\" {{ include \"complete\" }};\n\
+             xkb_compatibility \"ppkui\" {{ include \"complete\" }};\n\
+             xkb_symbols \"ppkui\" {{\n\
+             {}}};\n\
+             }};\n",
+            self.keysyms.len() + 9,
+            keycodes,
+            symbols
+        )
+    }
+
+    fn upload_keymap(&self) -> Result<(), String> {
+        let keymap = self.keymap();
+        debug!("uploading keymap:\n{}", keymap);
+
+        let name = CString::new("ppkui-keymap").unwrap_or_default();
+        let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC)
+            .map_err(|e| format!("can't create keymap file: {}", e))?;
+
+        // the file takes care of closing the fd, which is duplicated when
+        // the request is sent
+        let mut file = unsafe { File::from_raw_fd(fd) };
+
+        // the keymap has to be NUL terminated
+        file.write_all(keymap.as_bytes())
+            .and_then(|_| file.write_all(&[0]))
+            .map_err(|e| format!("can't write keymap: {}", e))?;
+
+        self.keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1.to_raw(),
+            file.as_raw_fd(),
+            keymap.len() as u32 + 1,
+        );
+
+        Ok(())
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        self.keyboard.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(s: &str) -> Result<KeyCombo, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn plain_key() {
+        let escape = combo("Escape").unwrap();
+
+        assert_eq!(escape.modifiers, 0);
+        assert_eq!(escape.keysym, "Escape");
+        assert_eq!(
+            combo("XF86AudioRaiseVolume").unwrap().to_string(),
+            "XF86AudioRaiseVolume"
+        );
+    }
+
+    #[test]
+    fn modifiers() {
+        let delete = combo("ctrl+alt+Delete").unwrap();
+
+        assert_eq!(delete.modifiers, MOD_CONTROL | MOD_ALT);
+        assert_eq!(delete.keysym, "Delete");
+        assert_eq!(
+            combo("super + shift + q").unwrap().modifiers,
+            MOD_SUPER | MOD_SHIFT
+        );
+        assert_eq!(
+            combo("alt+shift+control+logo+Tab").unwrap().to_string(),
+            "ctrl+shift+alt+super+Tab"
+        );
+    }
+
+    #[test]
+    fn modifier_names_ignore_case() {
+        assert_eq!(combo("CTRL+Shift+t"), combo("ctrl+shift+t"));
+        assert_eq!(combo("Win+t"), combo("super+t"));
+
+        // keysyms are case sensitive
+        assert_eq!(combo("ctrl+T").unwrap().keysym, "T");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(combo(""), Err("invalid key '' in ''".to_owned()));
+        assert_eq!(combo("ctrl+"), Err("invalid key '' in 'ctrl+'".to_owned()));
+        assert_eq!(
+            combo("hyper+x"),
+            Err("unknown modifier 'hyper' in 'hyper+x'".to_owned())
+        );
+        assert_eq!(
+            combo("ctrl+a b"),
+            Err("invalid key 'a b' in 'ctrl+a b'".to_owned())
+        );
+        assert!(combo("ctrl+}").is_err());
+    }
+}
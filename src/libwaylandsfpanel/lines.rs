@@ -0,0 +1,47 @@
+use std::io::{self, Read};
+
+/// Splits the output of a non-blocking reader into lines
+#[derive(Default)]
+pub struct LineReader {
+    /// Start of a line which hasn't been completed yet
+    pending: Vec<u8>,
+}
+
+impl LineReader {
+    /// Reads everything available, returns the complete lines and whether
+    /// the end of the stream has been reached
+    pub fn read(&mut self, reader: &mut impl Read) -> (Vec<String>, bool) {
+        let mut buffer = [0; 4096];
+        let mut ended = false;
+
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => {
+                    ended = true;
+                    break;
+                }
+                Ok(n) => self.pending.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(..) => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = vec![];
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line[..end]).into_owned());
+        }
+
+        // an unterminated last line still counts
+        if ended && !self.pending.is_empty() {
+            lines.push(String::from_utf8_lossy(&self.pending).into_owned());
+            self.pending.clear();
+        }
+
+        (lines, ended)
+    }
+}
@@ -0,0 +1,219 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use std::{
+    env,
+    io::{self, Write},
+    os::unix::net::UnixDatagram,
+    process,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Environment variable used to pick the log level, e.g. `PPKUI_LOG=trace`
+const LOG_ENV: &str = "PPKUI_LOG";
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Where log records end up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogTarget {
+    Stderr,
+    /// RFC 3164 messages sent to `/dev/log`
+    Syslog,
+    /// Native journal protocol, keeps the source location as separate fields
+    Journald,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stderr" => Ok(LogTarget::Stderr),
+            "syslog" => Ok(LogTarget::Syslog),
+            "journald" => Ok(LogTarget::Journald),
+            _ => Err(format!("unknown log target '{}'", s)),
+        }
+    }
+}
+
+enum Sink {
+    Stderr,
+    Syslog(UnixDatagram),
+    Journald(UnixDatagram),
+}
+
+struct Logger {
+    sink: Mutex<Sink>,
+}
+
+static LOGGER: Logger = Logger {
+    sink: Mutex::new(Sink::Stderr),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let sink = match self.sink.lock() {
+            Ok(sink) => sink,
+            Err(..) => return,
+        };
+
+        // there is nowhere left to report a failing log write to
+        let _ = match &*sink {
+            Sink::Stderr => writeln!(
+                io::stderr(),
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            Sink::Syslog(socket) => socket.send(&syslog_message(record)).map(|_| ()),
+            Sink::Journald(socket) => socket.send(&journald_message(record)).map(|_| ()),
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, taking the initial level from `PPKUI_LOG`
+pub fn init() {
+    let level = env::var(LOG_ENV)
+        .ok()
+        .and_then(|level| LevelFilter::from_str(&level).ok())
+        .unwrap_or(LevelFilter::Warn);
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Raises the log level to at least `Debug`; a more verbose level picked by
+/// `PPKUI_LOG` is kept as is
+pub fn enable_debug() {
+    if log::max_level() < LevelFilter::Debug {
+        log::set_max_level(LevelFilter::Debug);
+    }
+}
+
+/// Makes the log one level more verbose, used for repeated `-v` flags
+pub fn increase_verbosity() {
+    let level = match log::max_level() {
+        LevelFilter::Off => LevelFilter::Error,
+        LevelFilter::Error => LevelFilter::Warn,
+        LevelFilter::Warn => LevelFilter::Info,
+        LevelFilter::Info => LevelFilter::Debug,
+        LevelFilter::Debug | LevelFilter::Trace => LevelFilter::Trace,
+    };
+
+    log::set_max_level(level);
+}
+
+/// Only report errors
+pub fn quiet() {
+    log::set_max_level(LevelFilter::Error);
+}
+
+/// Switches the log output, falling back to stderr if the socket is missing
+pub fn set_target(target: LogTarget) {
+    let sink = match target {
+        LogTarget::Stderr => Ok(Sink::Stderr),
+        LogTarget::Syslog => connect(SYSLOG_SOCKET).map(Sink::Syslog),
+        LogTarget::Journald => connect(JOURNALD_SOCKET).map(Sink::Journald),
+    };
+
+    match sink {
+        Ok(sink) => {
+            if let Ok(mut current) = LOGGER.sink.lock() {
+                *current = sink;
+            }
+        }
+        Err(e) => log::warn!("can't log to {:?}, using stderr: {}", target, e),
+    }
+}
+
+fn connect(path: &str) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+fn identifier() -> String {
+    env::args()
+        .next()
+        .as_deref()
+        .and_then(|arg| arg.rsplit('/').next())
+        .unwrap_or("ppkui")
+        .to_owned()
+}
+
+/// Maps a log level to a syslog severity
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+fn syslog_message(record: &Record) -> Vec<u8> {
+    // facility "user" is 1
+    let priority = 8 + severity(record.level());
+
+    format!(
+        "<{}>{}[{}]: {}",
+        priority,
+        identifier(),
+        process::id(),
+        record.args()
+    )
+    .into_bytes()
+}
+
+fn journald_message(record: &Record) -> Vec<u8> {
+    let mut message = Vec::new();
+
+    add_journald_field(&mut message, "MESSAGE", &record.args().to_string());
+    add_journald_field(
+        &mut message,
+        "PRIORITY",
+        &severity(record.level()).to_string(),
+    );
+    add_journald_field(&mut message, "SYSLOG_IDENTIFIER", &identifier());
+    add_journald_field(&mut message, "TARGET", record.target());
+
+    if let Some(file) = record.file() {
+        add_journald_field(&mut message, "CODE_FILE", file);
+    }
+
+    if let Some(line) = record.line() {
+        add_journald_field(&mut message, "CODE_LINE", &line.to_string());
+    }
+
+    message
+}
+
+/// Values containing newlines have to be length-prefixed instead of using
+/// the plain `KEY=value` form
+fn add_journald_field(message: &mut Vec<u8>, key: &str, value: &str) {
+    message.extend_from_slice(key.as_bytes());
+
+    if value.contains('\n') {
+        message.push(b'\n');
+        message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        message.push(b'=');
+    }
+
+    message.extend_from_slice(value.as_bytes());
+    message.push(b'\n');
+}
@@ -0,0 +1,22 @@
+//! Bindings for protocols missing from wayland-protocols, generated by `build.rs`
+
+pub mod virtual_keyboard {
+    #![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+    #![allow(
+        non_upper_case_globals,
+        non_snake_case,
+        unused_imports,
+        static_mut_refs
+    )]
+    #![allow(missing_docs, clippy::all)]
+
+    pub(crate) use wayland_client::protocol::wl_seat;
+    pub(crate) use wayland_client::sys;
+    pub(crate) use wayland_client::{AnonymousObject, Attached, Main, Proxy, ProxyMap};
+    pub(crate) use wayland_commons::map::{Object, ObjectMetadata};
+    pub(crate) use wayland_commons::smallvec;
+    pub(crate) use wayland_commons::wire::{Argument, ArgumentType, Message, MessageDesc};
+    pub(crate) use wayland_commons::{Interface, MessageGroup};
+
+    include!(concat!(env!("OUT_DIR"), "/virtual_keyboard_client_api.rs"));
+}
@@ -0,0 +1,251 @@
+#[cfg(feature = "tiny-skia-backend")]
+mod skia_backend;
+#[cfg(not(feature = "tiny-skia-backend"))]
+mod software_backend;
+
+use super::WindowSize;
+
+use rusttype::{point, Scale};
+
+/// Drawing operations available to applications.
+///
+/// Colors are premultiplied `[a, r, g, b]` (see `config::rgba_to_color`) and
+/// everything is composited with the source-over operator, so translucent
+/// colors stay translucent in the resulting buffer.
+pub trait Renderer {
+    /// Replaces the whole surface with a color, ignoring the clip rectangle
+    fn clear(&mut self, color: [u8; 4]);
+
+    /// Restricts all following drawing to a rectangle, `None` removes the restriction
+    fn set_clip(&mut self, clip: Option<Rect>);
+
+    fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: [u8; 4]);
+
+    /// Draws text with its top left corner at `pos`
+    fn draw_text(&mut self, text: &str, font: &Font, size: f32, pos: (i32, i32), color: [u8; 4]);
+
+    /// Draws an image scaled to fill a rectangle
+    #[allow(dead_code)]
+    fn draw_image(&mut self, image: &Image, rect: Rect);
+
+    fn fill_rect(&mut self, rect: Rect, color: [u8; 4]) {
+        self.fill_rounded_rect(rect, 0., color);
+    }
+}
+
+/// Creates the renderer picked at build time over an `Argb8888` buffer
+pub fn new_renderer<'a>(buffer: &'a mut [u8], size: WindowSize) -> impl Renderer + 'a {
+    #[cfg(not(feature = "tiny-skia-backend"))]
+    return software_backend::SoftwareRenderer::new(buffer, size);
+
+    #[cfg(feature = "tiny-skia-backend")]
+    return skia_backend::SkiaRenderer::new(buffer, size);
+}
+
+/// Limits all drawing, `clear` included, to an area of a buffer holding the
+/// previous frame, which has to be emptied there beforehand
+pub struct AreaRenderer<R: Renderer> {
+    inner: R,
+    area: Rect,
+    /// Set by the application
+    clip: Option<Rect>,
+}
+
+impl<R: Renderer> AreaRenderer<R> {
+    pub fn new(mut inner: R, area: Rect) -> Self {
+        inner.set_clip(Some(area));
+
+        AreaRenderer {
+            inner,
+            area,
+            clip: None,
+        }
+    }
+}
+
+impl<R: Renderer> Renderer for AreaRenderer<R> {
+    fn clear(&mut self, color: [u8; 4]) {
+        // the area is empty, so blending over it replaces it
+        self.inner.set_clip(Some(self.area));
+        self.inner.fill_rect(self.area, color);
+        self.set_clip(self.clip);
+    }
+
+    fn set_clip(&mut self, clip: Option<Rect>) {
+        self.clip = clip;
+
+        let area = match clip {
+            Some(clip) => {
+                clip.intersect(&self.area)
+                    .unwrap_or(Rect::new(self.area.x, self.area.y, 0, 0))
+            }
+            None => self.area,
+        };
+        self.inner.set_clip(Some(area));
+    }
+
+    fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: [u8; 4]) {
+        self.inner.fill_rounded_rect(rect, radius, color);
+    }
+
+    fn draw_text(&mut self, text: &str, font: &Font, size: f32, pos: (i32, i32), color: [u8; 4]) {
+        self.inner.draw_text(text, font, size, pos, color);
+    }
+
+    fn draw_image(&mut self, image: &Image, rect: Rect) {
+        self.inner.draw_image(image, rect);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64
+            && x < self.right() as f64
+            && y >= self.y as f64
+            && y < self.bottom() as f64
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = std::cmp::max(self.x, other.x);
+        let y = std::cmp::max(self.y, other.y);
+        let right = std::cmp::min(self.right(), other.right());
+        let bottom = std::cmp::min(self.bottom(), other.bottom());
+
+        if right <= x || bottom <= y {
+            None
+        } else {
+            Some(Rect::new(x, y, (right - x) as u32, (bottom - y) as u32))
+        }
+    }
+
+    /// The smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = std::cmp::min(self.x, other.x);
+        let y = std::cmp::min(self.y, other.y);
+        let right = std::cmp::max(self.right(), other.right());
+        let bottom = std::cmp::max(self.bottom(), other.bottom());
+
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+}
+
+/// A parsed font, cheap to clone
+#[derive(Clone)]
+pub struct Font {
+    font: rusttype::Font<'static>,
+}
+
+impl Font {
+    pub fn from_data(data: Vec<u8>) -> Option<Self> {
+        Some(Font {
+            font: rusttype::Font::try_from_vec(data)?,
+        })
+    }
+
+    /// Width in pixels of the text when drawn with `draw_text`
+    pub fn text_width(&self, text: &str, size: f32) -> u32 {
+        let glyphs = self.layout(text, size);
+
+        let min_x = glyphs
+            .first()
+            .map(|g| match g.pixel_bounding_box() {
+                Some(bb) => bb.min.x,
+                None => g.position().x as i32,
+            })
+            .unwrap_or(0);
+        let max_x = glyphs
+            .last()
+            .map(|g| match g.pixel_bounding_box() {
+                Some(bb) => bb.max.x,
+                None => (g.position().x + g.unpositioned().h_metrics().advance_width) as i32,
+            })
+            .unwrap_or(0);
+
+        std::cmp::max(max_x - min_x, 0) as u32
+    }
+
+    /// Lays out a single line of text starting at the origin, with the
+    /// baseline placed so that the top of the line is at 0
+    fn layout(&self, text: &str, size: f32) -> Vec<rusttype::PositionedGlyph<'_>> {
+        let scale = Scale::uniform(size);
+        let ascent = self.font.v_metrics(scale).ascent;
+
+        self.font.layout(text, scale, point(0., ascent)).collect()
+    }
+}
+
+/// Premultiplied pixels, stored in the same byte order as the surface buffer
+#[allow(dead_code)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image from premultiplied `[a, r, g, b]` pixels
+    pub fn from_argb(width: u32, height: u32, pixels: &[[u8; 4]]) -> Option<Self> {
+        if pixels.len() != (width * height) as usize {
+            return None;
+        }
+
+        Some(Image {
+            width,
+            height,
+            data: pixels.iter().flat_map(|p| to_native(*p).to_vec()).collect(),
+        })
+    }
+
+    /// Replaces every color with a single one, the alpha channel of the
+    /// image is used as a mask
+    #[allow(dead_code)]
+    pub fn tinted(&self, color: [u8; 4]) -> Image {
+        let data = self
+            .data
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                // alpha is the last byte in the native order
+                let coverage = u16::from(pixel[3]);
+                let tinted = color.map(|c| (u16::from(c) * coverage / 255) as u8);
+                to_native(tinted).to_vec()
+            })
+            .collect();
+
+        Image {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+/// Converts `[a, r, g, b]` to the in-memory byte order of `Argb8888`,
+/// which is defined as little endian by the protocol
+fn to_native(color: [u8; 4]) -> [u8; 4] {
+    u32::from_be_bytes(color).to_le_bytes()
+}
@@ -0,0 +1,245 @@
+use super::{Font, Image, Rect, Renderer};
+use crate::libwaylandsfpanel::WindowSize;
+
+use tiny_skia::{
+    FillRule, FilterQuality, IntSize, Mask, Paint, PathBuilder, Pixmap, PixmapMut, PixmapPaint,
+    PixmapRef, PremultipliedColorU8, Transform,
+};
+
+/// Antialiased software renderer based on tiny-skia.
+///
+/// tiny-skia works on RGBA pixels, while the surface buffer is BGRA in memory,
+/// so red and blue are swapped on every color passed to it. Blending treats
+/// all color channels the same way, which makes the result match the buffer
+/// layout without any conversion pass.
+pub struct SkiaRenderer<'a> {
+    pixmap: PixmapMut<'a>,
+    clip: Option<Rect>,
+    clip_mask: Option<Mask>,
+}
+
+impl<'a> SkiaRenderer<'a> {
+    pub fn new(buffer: &'a mut [u8], size: WindowSize) -> Self {
+        let pixmap = PixmapMut::from_bytes(buffer, size.0, size.1)
+            .expect("surface buffer does not match its size");
+
+        SkiaRenderer {
+            pixmap,
+            clip: None,
+            clip_mask: None,
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.pixmap.width(), self.pixmap.height())
+    }
+
+    /// The area drawing is currently allowed in, `None` if that's nothing
+    fn visible_area(&self) -> Option<Rect> {
+        match self.clip {
+            Some(clip) => clip.intersect(&self.bounds()),
+            None => Some(self.bounds()),
+        }
+    }
+}
+
+impl<'a> Renderer for SkiaRenderer<'a> {
+    fn clear(&mut self, color: [u8; 4]) {
+        let [a, r, g, b] = color;
+        let pixel = [b, g, r, a];
+
+        for chunk in self.pixmap.data_mut().chunks_exact_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+
+    fn set_clip(&mut self, clip: Option<Rect>) {
+        self.clip = clip;
+        self.clip_mask = None;
+
+        let area = match clip.and_then(|clip| clip.intersect(&self.bounds())) {
+            Some(area) => area,
+            // nothing will be drawn anyway
+            None => return,
+        };
+
+        if area == self.bounds() {
+            return;
+        }
+
+        if let (Some(mut mask), Some(rect)) = (
+            Mask::new(self.pixmap.width(), self.pixmap.height()),
+            to_skia_rect(area),
+        ) {
+            let path = PathBuilder::from_rect(rect);
+            mask.fill_path(&path, FillRule::Winding, false, Transform::identity());
+            self.clip_mask = Some(mask);
+        }
+    }
+
+    fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: [u8; 4]) {
+        if rect.intersect(&self.bounds()).is_none() || self.visible_area().is_none() {
+            return;
+        }
+
+        let paint = solid_paint(color);
+        let mask = self.clip_mask.as_ref();
+
+        if radius <= 0. {
+            if let Some(rect) = to_skia_rect(rect) {
+                self.pixmap
+                    .fill_rect(rect, &paint, Transform::identity(), mask);
+            }
+        } else if let Some(path) = rounded_rect_path(rect, radius) {
+            self.pixmap.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                mask,
+            );
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, font: &Font, size: f32, pos: (i32, i32), color: [u8; 4]) {
+        let area = match self.visible_area() {
+            Some(area) => area,
+            None => return,
+        };
+
+        let glyphs = font.layout(text, size);
+        let text_area = glyphs
+            .iter()
+            .filter_map(|glyph| glyph.pixel_bounding_box())
+            .map(|bounding_box| {
+                Rect::new(
+                    pos.0 + bounding_box.min.x,
+                    pos.1 + bounding_box.min.y,
+                    bounding_box.width() as u32,
+                    bounding_box.height() as u32,
+                )
+            })
+            .reduce(|a, b| a.union(&b));
+
+        let text_area = match text_area.and_then(|text_area| text_area.intersect(&area)) {
+            Some(text_area) => text_area,
+            None => return,
+        };
+
+        // only covers the visible part of the text, glyphs may overlap so
+        // every pixel keeps the highest coverage
+        let mut coverage = vec![0u8; (text_area.width * text_area.height) as usize];
+
+        for glyph in &glyphs {
+            if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, value| {
+                    let x = x as i32 + pos.0 + bounding_box.min.x;
+                    let y = y as i32 + pos.1 + bounding_box.min.y;
+
+                    if text_area.contains(x as f64, y as f64) {
+                        let index =
+                            (y - text_area.y) as u32 * text_area.width + (x - text_area.x) as u32;
+                        let pixel = &mut coverage[index as usize];
+                        *pixel = (*pixel).max((value * 255.).round() as u8);
+                    }
+                });
+            }
+        }
+
+        // scaling every channel by the same factor keeps the color premultiplied
+        let [a, r, g, b] = color;
+        let pixels = coverage
+            .iter()
+            .flat_map(|&value| [b, g, r, a].map(|c| (u16::from(c) * u16::from(value) / 255) as u8))
+            .collect();
+
+        if let Some(pixmap) = IntSize::from_wh(text_area.width, text_area.height)
+            .and_then(|size| Pixmap::from_vec(pixels, size))
+        {
+            self.pixmap.draw_pixmap(
+                text_area.x,
+                text_area.y,
+                pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    fn draw_image(&mut self, image: &Image, rect: Rect) {
+        if rect.width == 0 || rect.height == 0 || self.visible_area().is_none() {
+            return;
+        }
+
+        // images are stored in the buffer byte order, which is swapped the
+        // same way as every other color here
+        let pixmap = match PixmapRef::from_bytes(&image.data, image.width, image.height) {
+            Some(pixmap) => pixmap,
+            None => return,
+        };
+
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bilinear,
+            ..PixmapPaint::default()
+        };
+
+        let transform = Transform::from_row(
+            rect.width as f32 / image.width as f32,
+            0.,
+            0.,
+            rect.height as f32 / image.height as f32,
+            rect.x as f32,
+            rect.y as f32,
+        );
+
+        self.pixmap
+            .draw_pixmap(0, 0, pixmap, &paint, transform, self.clip_mask.as_ref());
+    }
+}
+
+/// Builds a paint from a premultiplied `[a, r, g, b]` color, with red and blue swapped
+fn solid_paint(color: [u8; 4]) -> Paint<'static> {
+    let [a, r, g, b] = color;
+    let mut paint = Paint::default();
+
+    if let Some(color) = PremultipliedColorU8::from_rgba(b, g, r, a) {
+        let color = color.demultiply();
+        paint.set_color_rgba8(color.red(), color.green(), color.blue(), color.alpha());
+    }
+
+    paint
+}
+
+fn to_skia_rect(rect: Rect) -> Option<tiny_skia::Rect> {
+    tiny_skia::Rect::from_xywh(
+        rect.x as f32,
+        rect.y as f32,
+        rect.width as f32,
+        rect.height as f32,
+    )
+}
+
+fn rounded_rect_path(rect: Rect, radius: f32) -> Option<tiny_skia::Path> {
+    let (x, y) = (rect.x as f32, rect.y as f32);
+    let (right, bottom) = (rect.right() as f32, rect.bottom() as f32);
+    let radius = radius
+        .min(rect.width as f32 / 2.)
+        .min(rect.height as f32 / 2.);
+
+    // distance of the control points from the corner to approximate a circle
+    let k = radius * (1. - 0.552_284_8);
+
+    let mut pb = PathBuilder::new();
+    pb.move_to(x + radius, y);
+    pb.line_to(right - radius, y);
+    pb.cubic_to(right - k, y, right, y + k, right, y + radius);
+    pb.line_to(right, bottom - radius);
+    pb.cubic_to(right, bottom - k, right - k, bottom, right - radius, bottom);
+    pb.line_to(x + radius, bottom);
+    pb.cubic_to(x + k, bottom, x, bottom - k, x, bottom - radius);
+    pb.line_to(x, y + radius);
+    pb.cubic_to(x, y + k, x + k, y, x + radius, y);
+    pb.close();
+    pb.finish()
+}
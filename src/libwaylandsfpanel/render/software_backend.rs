@@ -0,0 +1,150 @@
+use super::{to_native, Font, Image, Rect, Renderer};
+use crate::libwaylandsfpanel::WindowSize;
+
+/// Renderer doing plain per-pixel blending, with antialiased corners and
+/// text but without any dependency of its own.
+pub struct SoftwareRenderer<'a> {
+    buffer: &'a mut [u8],
+    width: usize,
+    height: usize,
+    clip: Rect,
+}
+
+impl<'a> SoftwareRenderer<'a> {
+    pub fn new(buffer: &'a mut [u8], size: WindowSize) -> Self {
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        assert!(buffer.len() == 4 * width * height);
+
+        SoftwareRenderer {
+            buffer,
+            width,
+            height,
+            clip: Rect::new(0, 0, size.0, size.1),
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width as u32, self.height as u32)
+    }
+
+    /// Composites a premultiplied native color scaled by coverage over a
+    /// single pixel, the caller has to check the clip rectangle
+    fn blend_pixel(&mut self, x: usize, y: usize, color: [u8; 4], coverage: f32) {
+        let base = 4 * (y * self.width + x);
+        let src_alpha = f32::from(color[3]) / 255. * coverage;
+
+        for (dst, src) in self.buffer[base..base + 4].iter_mut().zip(color.iter()) {
+            let value = f32::from(*src) * coverage + f32::from(*dst) * (1. - src_alpha);
+            *dst = value.round().min(255.) as u8;
+        }
+    }
+}
+
+impl<'a> Renderer for SoftwareRenderer<'a> {
+    fn clear(&mut self, color: [u8; 4]) {
+        let pixel = to_native(color);
+        for chunk in self.buffer.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+
+    fn set_clip(&mut self, clip: Option<Rect>) {
+        let bounds = self.bounds();
+
+        self.clip = match clip {
+            Some(clip) => clip.intersect(&bounds).unwrap_or(Rect::new(0, 0, 0, 0)),
+            None => bounds,
+        };
+    }
+
+    fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, color: [u8; 4]) {
+        let area = match rect.intersect(&self.clip) {
+            Some(area) => area,
+            None => return,
+        };
+
+        let color = to_native(color);
+        let radius = radius
+            .min(rect.width as f32 / 2.)
+            .min(rect.height as f32 / 2.);
+
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                let coverage = corner_coverage(&rect, radius, x, y);
+                if coverage > 0. {
+                    self.blend_pixel(x as usize, y as usize, color, coverage);
+                }
+            }
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, font: &Font, size: f32, pos: (i32, i32), color: [u8; 4]) {
+        let color = to_native(color);
+        let clip = self.clip;
+
+        for glyph in font.layout(text, size) {
+            if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    let x = x as i32 + pos.0 + bounding_box.min.x;
+                    let y = y as i32 + pos.1 + bounding_box.min.y;
+
+                    if clip.contains(x as f64, y as f64) {
+                        self.blend_pixel(x as usize, y as usize, color, coverage);
+                    }
+                });
+            }
+        }
+    }
+
+    fn draw_image(&mut self, image: &Image, rect: Rect) {
+        let area = match rect.intersect(&self.clip) {
+            Some(area) => area,
+            None => return,
+        };
+
+        // nearest neighbour sampling
+        for y in area.y..area.bottom() {
+            let src_y = (y - rect.y) as u64 * image.height as u64 / rect.height as u64;
+
+            for x in area.x..area.right() {
+                let src_x = (x - rect.x) as u64 * image.width as u64 / rect.width as u64;
+                let base = 4 * (src_y * image.width as u64 + src_x) as usize;
+
+                let mut color = [0; 4];
+                color.copy_from_slice(&image.data[base..base + 4]);
+                self.blend_pixel(x as usize, y as usize, color, 1.);
+            }
+        }
+    }
+}
+
+/// Antialiased coverage of a pixel by a rectangle with rounded corners
+fn corner_coverage(rect: &Rect, radius: f32, x: i32, y: i32) -> f32 {
+    if radius <= 0. {
+        return 1.;
+    }
+
+    // pixel center relative to the rectangle
+    let px = (x - rect.x) as f32 + 0.5;
+    let py = (y - rect.y) as f32 + 0.5;
+    let (width, height) = (rect.width as f32, rect.height as f32);
+
+    let cx = if px < radius {
+        radius
+    } else if px > width - radius {
+        width - radius
+    } else {
+        return 1.;
+    };
+
+    let cy = if py < radius {
+        radius
+    } else if py > height - radius {
+        height - radius
+    } else {
+        return 1.;
+    };
+
+    let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+    (radius - distance + 0.5).clamp(0., 1.)
+}
@@ -0,0 +1,134 @@
+//! Windows of every client, from `zwlr_foreign_toplevel_manager_v1`
+
+use smithay_client_toolkit::reexports::{
+    client::{protocol::wl_seat, Main},
+    protocols::wlr::unstable::foreign_toplevel::v1::client::{
+        zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+        zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+    },
+};
+
+use std::{cell::RefCell, convert::TryInto, rc::Rc};
+
+/// A window as the compositor has described it last
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Toplevel {
+    /// Stays the same while the window exists
+    pub id: u32,
+    pub title: String,
+    pub app_id: String,
+    pub activated: bool,
+}
+
+struct Entry {
+    handle: Main<ZwlrForeignToplevelHandleV1>,
+    /// Changes which take effect with the next `done` event
+    pending: Toplevel,
+    /// Nothing until the first `done` event
+    current: Option<Toplevel>,
+}
+
+#[derive(Default)]
+pub struct Toplevels {
+    /// Activates the windows
+    seat: Option<wl_seat::WlSeat>,
+    entries: Vec<Entry>,
+}
+
+impl Toplevels {
+    /// Starts tracking the windows, `changed` is called whenever the list
+    /// has changed
+    pub fn listen(
+        toplevels: &Rc<RefCell<Self>>,
+        manager: &Main<ZwlrForeignToplevelManagerV1>,
+        seat: &wl_seat::WlSeat,
+        changed: Rc<dyn Fn()>,
+    ) {
+        toplevels.borrow_mut().seat = Some(seat.clone());
+
+        let toplevels = Rc::clone(toplevels);
+        manager.quick_assign(move |_, event, _| {
+            let handle = match event {
+                zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => toplevel,
+                _ => return,
+            };
+            let id = handle.as_ref().id();
+
+            let toplevels_handle = Rc::clone(&toplevels);
+            let changed = Rc::clone(&changed);
+            handle.quick_assign(move |_, event, _| {
+                // the list is borrowed again by `changed`
+                let updated = toplevels_handle.borrow_mut().update(id, event);
+                if updated {
+                    changed();
+                }
+            });
+
+            toplevels.borrow_mut().entries.push(Entry {
+                handle,
+                pending: Toplevel {
+                    id,
+                    ..Toplevel::default()
+                },
+                current: None,
+            });
+        });
+    }
+
+    /// Handles an event of a window, returns whether the list has changed
+    fn update(&mut self, id: u32, event: zwlr_foreign_toplevel_handle_v1::Event) -> bool {
+        use zwlr_foreign_toplevel_handle_v1::Event;
+
+        let index = match self.entries.iter().position(|entry| entry.pending.id == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let entry = &mut self.entries[index];
+
+        match event {
+            Event::Title { title } => entry.pending.title = title,
+            Event::AppId { app_id } => entry.pending.app_id = app_id,
+            Event::State { state } => {
+                let activated = zwlr_foreign_toplevel_handle_v1::State::Activated.to_raw();
+                entry.pending.activated = state
+                    .chunks_exact(4)
+                    .any(|value| u32::from_ne_bytes(value.try_into().unwrap()) == activated);
+            }
+            Event::Done => {
+                entry.current = Some(entry.pending.clone());
+                return true;
+            }
+            Event::Closed => {
+                let entry = self.entries.remove(index);
+                entry.handle.destroy();
+                return entry.current.is_some();
+            }
+            _ => {}
+        }
+
+        false
+    }
+
+    pub fn list(&self) -> Vec<Toplevel> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.current.clone())
+            .collect()
+    }
+
+    pub fn activate(&self, id: u32) {
+        if let (Some(entry), Some(seat)) = (self.entry(id), &self.seat) {
+            entry.handle.activate(seat);
+        }
+    }
+
+    pub fn close(&self, id: u32) {
+        if let Some(entry) = self.entry(id) {
+            entry.handle.close();
+        }
+    }
+
+    fn entry(&self, id: u32) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.pending.id == id)
+    }
+}
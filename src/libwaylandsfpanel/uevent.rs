@@ -0,0 +1,106 @@
+//! Kernel device events from a netlink socket
+
+use nix::libc;
+use nix::sys::socket::{bind, NetlinkAddr, SockAddr};
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+
+/// Multicast group of the events sent by the kernel itself
+const KERNEL_GROUP: u32 = 1;
+
+/// A device has been added, removed or has changed
+#[derive(Debug, Default)]
+pub struct Uevent {
+    pub action: String,
+    pub devpath: String,
+    pub subsystem: String,
+}
+
+/// Opens a non-blocking socket receiving the events of every device
+pub fn open() -> io::Result<File> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // closes the socket if binding fails
+    let socket = unsafe { File::from_raw_fd(fd) };
+
+    bind(fd, &SockAddr::Netlink(NetlinkAddr::new(0, KERNEL_GROUP)))
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    Ok(socket)
+}
+
+/// Reads every event which has arrived
+pub fn read(socket: &mut File) -> Vec<Uevent> {
+    let mut buffer = [0; 8192];
+    let mut events = vec![];
+
+    loop {
+        match socket.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => events.push(parse(&buffer[..n])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(..) => break,
+        }
+    }
+
+    events
+}
+
+/// Parses `action@devpath` followed by `KEY=value` lines, all separated by
+/// null bytes
+fn parse(message: &[u8]) -> Uevent {
+    let mut event = Uevent::default();
+
+    for field in message.split(|b| *b == 0).skip(1) {
+        let field = String::from_utf8_lossy(field);
+        let (key, value) = match field.find('=') {
+            Some(i) => (&field[..i], &field[i + 1..]),
+            None => continue,
+        };
+
+        match key {
+            "ACTION" => event.action = value.to_owned(),
+            "DEVPATH" => event.devpath = value.to_owned(),
+            "SUBSYSTEM" => event.subsystem = value.to_owned(),
+            _ => {}
+        }
+    }
+
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_fields() {
+        let message = b"change@/devices/BAT0\0ACTION=change\0DEVPATH=/devices/BAT0\0\
+                        SUBSYSTEM=power_supply\0SEQNUM=1234\0POWER_SUPPLY_NAME=BAT0\0";
+        let event = parse(message);
+
+        assert_eq!(event.action, "change");
+        assert_eq!(event.devpath, "/devices/BAT0");
+        assert_eq!(event.subsystem, "power_supply");
+    }
+
+    #[test]
+    fn ignores_the_header_and_broken_fields() {
+        let event = parse(b"add@/devices/AC\0nonsense\0SUBSYSTEM=power_supply");
+
+        assert_eq!(event.action, "");
+        assert_eq!(event.devpath, "");
+        assert_eq!(event.subsystem, "power_supply");
+    }
+}
@@ -4,11 +4,12 @@ use smithay_client_toolkit::reexports::protocols::wlr::unstable::layer_shell::v1
     zwlr_layer_shell_v1, zwlr_layer_surface_v1,
 };
 
-use andrew::{shapes::rectangle, text, Canvas};
+use libwaylandsfpanel::{Font, Rect, Renderer};
+use log::{debug, error};
 
 use std::{
     cmp, env,
-    io::{self, prelude::*, Read, Write},
+    io::{self, prelude::*, Write},
     process,
 };
 
@@ -22,7 +23,7 @@ struct Menu {
     /// registered buttons
     click_targets: Vec<ClickTarget>,
     /// static config stuff
-    font_data: Vec<u8>,
+    font: Font,
     cfg: Config,
     launcher_config: LauncherConfig,
     colors: ColorConfig,
@@ -40,7 +41,7 @@ impl Clone for Menu {
             pointer_start: None,
             pointer_current: None,
             click_targets: vec![],
-            font_data: self.font_data.clone(),
+            font: self.font.clone(),
             cfg: self.cfg.clone(),
             launcher_config: self.launcher_config.clone(),
             colors: self.colors.clone(),
@@ -69,9 +70,12 @@ impl Menu {
 
             match matching_click_handler {
                 Some(ClickHandler::Selected(cmd)) => {
-                    io::stdout().write_all(cmd.as_bytes()).unwrap();
-                    io::stdout().write_all("\n".as_bytes()).unwrap();
-                    io::stdout().flush().unwrap();
+                    debug!("selected '{}'", cmd);
+
+                    let mut stdout = io::stdout();
+                    if let Err(e) = writeln!(stdout, "{}", cmd).and_then(|_| stdout.flush()) {
+                        error!("can't write selection: {}", e);
+                    }
                     return true;
                 }
                 None => {}
@@ -83,27 +87,44 @@ impl Menu {
 }
 
 impl libwaylandsfpanel::Application for Menu {
-    fn new() -> Self {
+    fn new(_handle: libwaylandsfpanel::Handle) -> Self {
         let cfg = match parse_menu(env::args()) {
             Ok(args) => args,
             Err(message) => {
-                eprintln!("{}", message);
+                error!("{}", message);
 
                 process::exit(1);
             }
         };
 
-        let colors = cfg.get_color_config();
+        let colors = match cfg.get_color_config() {
+            Ok(colors) => colors,
+            Err(message) => {
+                error!("{}", message);
+
+                process::exit(1);
+            }
+        };
 
         let launcher_config = cfg.clone().launcher.unwrap();
 
         let options = get_options();
 
-        let mut font_data = Vec::new();
-        std::fs::File::open(launcher_config.font.clone())
-            .unwrap()
-            .read_to_end(&mut font_data)
-            .unwrap();
+        let font = match std::fs::read(&launcher_config.font) {
+            Ok(font_data) => match Font::from_data(font_data) {
+                Some(font) => font,
+                None => {
+                    error!("can't parse font '{}'", launcher_config.font);
+
+                    process::exit(1);
+                }
+            },
+            Err(e) => {
+                error!("can't read font '{}': {}", launcher_config.font, e);
+
+                process::exit(1);
+            }
+        };
 
         Menu {
             list_offset: 0,
@@ -111,7 +132,7 @@ impl libwaylandsfpanel::Application for Menu {
             pointer_start: None,
             pointer_current: None,
             click_targets: vec![],
-            font_data,
+            font,
             cfg,
             launcher_config,
             colors,
@@ -119,6 +140,10 @@ impl libwaylandsfpanel::Application for Menu {
         }
     }
 
+    fn is_opaque(&self) -> bool {
+        self.colors.is_opaque()
+    }
+
     fn settings(&self) -> libwaylandsfpanel::ApplicationSettings {
         let want_height = cmp::min(
             self.launcher_config.line_height * self.launcher_config.max_lines,
@@ -137,17 +162,12 @@ impl libwaylandsfpanel::Application for Menu {
         }
     }
 
-    fn draw(&mut self, size: libwaylandsfpanel::WindowSize, buf: &mut [u8]) {
+    fn draw(&mut self, size: libwaylandsfpanel::WindowSize, renderer: &mut dyn Renderer) {
         let width = size.0 as i32;
         let height = size.1 as i32;
 
-        let mut canvas = andrew::Canvas::new(
-            buf,
-            width as usize,
-            height as usize,
-            4 * width as usize,
-            andrew::Endian::native(),
-        );
+        renderer.clear(self.colors.background_color);
+        self.click_targets.clear();
 
         // Draw buttons
         let button_height = self.launcher_config.line_height as usize;
@@ -165,45 +185,32 @@ impl libwaylandsfpanel::Application for Menu {
 
         let create_button = move |colors: &ColorConfig,
                                   label: String,
-                                  font_data: &[u8],
-                                  canvas: &mut Canvas,
+                                  font: &Font,
+                                  renderer: &mut dyn Renderer,
                                   pointer_engaged: bool,
                                   pointer: Option<libwaylandsfpanel::PointerPosition>,
                                   next_draw_at: &mut i32| {
-            // first button? nice
+            let current_draw_at = *next_draw_at;
+            *next_draw_at += button_height as i32;
 
-            // ugh, I don't think we can currently draw stuff out of bounds
-            // that's a bit disappointing
-            if *next_draw_at < 0 || *next_draw_at > height {
-                *next_draw_at += button_height as i32;
+            // rows partially scrolled out of the surface are still drawn,
+            // the renderer clips them
+            if current_draw_at + button_height as i32 <= 0 || current_draw_at >= height {
                 return None;
             }
-            let current_draw_at = *next_draw_at as usize;
-
-            let mut text = text::Text::new(
-                (0, 0),
-                colors.text_color,
-                font_data,
-                text_h,
-                1.0,
-                label.clone(),
-            );
-            let text_width = text.get_width();
+
+            let text_width = font.text_width(&label, text_h) as i32;
             // button should take the whole screen
-            let button_width = width as usize;
-            let block_pos = (0, current_draw_at);
+            let block = Rect::new(0, current_draw_at, width as u32, button_height as u32);
             let text_pos = (
-                (button_width - text_width) / 2,
-                current_draw_at + ((button_height as f32 - text_h) / 2.) as usize,
+                (width - text_width) / 2,
+                current_draw_at + ((button_height as f32 - text_h) / 2.) as i32,
             );
-            text.pos = text_pos;
-            let size = (button_width as usize, button_height as usize);
 
             // create a click target
             let click_target = ClickTarget {
-                position: block_pos,
-                size,
-                handler: ClickHandler::Selected(label),
+                rect: block,
+                handler: ClickHandler::Selected(label.clone()),
             };
 
             // a very ugly way to check if this click target is hovered
@@ -214,7 +221,7 @@ impl libwaylandsfpanel::Application for Menu {
                         click_position.0,
                         click_position.1 - swipe_dist as f64,
                     );
-                    if let Some(..) = click_target.process_click(adj_pos) {
+                    if click_target.process_click(adj_pos).is_some() {
                         retval = true;
                     }
                 };
@@ -226,11 +233,12 @@ impl libwaylandsfpanel::Application for Menu {
                 true => colors.button_hover_color,
             };
 
-            let block = rectangle::Rectangle::new(block_pos, size, None, Some(color));
-            canvas.draw(&block);
-            canvas.draw(&text);
+            renderer.fill_rect(block, color);
 
-            *next_draw_at += button_height as i32;
+            // keep long labels inside of the row
+            renderer.set_clip(Some(block));
+            renderer.draw_text(&label, font, text_h, text_pos, colors.text_color);
+            renderer.set_clip(None);
 
             Some(click_target)
         };
@@ -239,15 +247,15 @@ impl libwaylandsfpanel::Application for Menu {
             let click_target = create_button(
                 &self.colors,
                 button,
-                &self.font_data,
-                &mut canvas,
+                &self.font,
+                renderer,
                 self.pointer_engaged,
                 self.pointer_start,
                 &mut next_draw_at,
             );
 
-            if click_target.is_some() {
-                self.click_targets.push(click_target.unwrap());
+            if let Some(click_target) = click_target {
+                self.click_targets.push(click_target);
             }
         }
     }
@@ -322,12 +330,11 @@ fn swipe_distance(
     current: Option<libwaylandsfpanel::PointerPosition>,
     engaged: bool,
 ) -> Option<i32> {
-    if start.is_none() || current.is_none() || !engaged {
-        None
-    } else if start.unwrap() != current.unwrap() {
-        Some((start.unwrap().1 - current.unwrap().1) as i32)
-    } else {
-        None
+    match (start, current) {
+        (Some(start), Some(current)) if engaged && start != current => {
+            Some((start.1 - current.1) as i32)
+        }
+        _ => None,
     }
 }
 
@@ -338,8 +345,7 @@ enum ClickHandler {
 }
 
 struct ClickTarget {
-    position: (usize, usize),
-    size: (usize, usize),
+    rect: Rect,
     handler: ClickHandler,
 }
 
@@ -348,17 +354,7 @@ impl ClickTarget {
         &self,
         click_position: libwaylandsfpanel::PointerPosition,
     ) -> Option<ClickHandler> {
-        let click_x = click_position.0;
-        let click_y = click_position.1;
-
-        let (position_x, position_y) = (self.position.0 as f64, self.position.1 as f64);
-        let (size_x, size_y) = (self.size.0 as f64, self.size.1 as f64);
-
-        if click_x >= position_x
-            && click_x < position_x + size_x
-            && click_y >= position_y
-            && click_y < position_y + size_y
-        {
+        if self.rect.contains(click_position.0, click_position.1) {
             Some(self.handler.clone())
         } else {
             None
@@ -371,12 +367,9 @@ fn is_clicking(
     current: Option<libwaylandsfpanel::PointerPosition>,
     engaged: bool,
 ) -> bool {
-    if start.is_none() || current.is_none() || !engaged {
-        false
-    } else if start.unwrap() == current.unwrap() {
-        true
-    } else {
-        false
+    match (start, current) {
+        (Some(start), Some(current)) => engaged && start == current,
+        _ => false,
     }
 }
 
@@ -392,13 +385,24 @@ pub fn parse_menu(args: impl Iterator<Item = String>) -> Result<Config, String>
         match args.next().as_deref() {
             // config file location
             Some("-c") | Some("--config") => {
-                let arg = args.next();
-
-                if arg.is_some() {
-                    config_file = arg.unwrap();
+                if let Some(arg) = args.next() {
+                    config_file = arg;
                 }
             }
 
+            // protocol event tracing, see also PPKUI_LOG
+            Some("-d") | Some("--debug") => libwaylandsfpanel::enable_debug(),
+
+            Some("-v") | Some("--verbose") => libwaylandsfpanel::increase_verbosity(),
+
+            Some("-q") | Some("--quiet") => libwaylandsfpanel::quiet(),
+
+            // stderr, syslog or journald
+            Some("--log-target") => {
+                let target = args.next().unwrap_or_default().parse()?;
+                libwaylandsfpanel::set_log_target(target);
+            }
+
             // TODO
             Some("-i") => {}
 
@@ -413,10 +417,8 @@ pub fn parse_menu(args: impl Iterator<Item = String>) -> Result<Config, String>
             }
 
             Some("-p") | Some("--prompt") => {
-                let arg = args.next();
-
-                if arg.is_some() {
-                    prompt = Some(arg.unwrap());
+                if let Some(arg) = args.next() {
+                    prompt = Some(arg);
                 }
             }
 
@@ -426,20 +428,14 @@ pub fn parse_menu(args: impl Iterator<Item = String>) -> Result<Config, String>
         }
     }
 
-    let mut config_data = Vec::new();
-    std::fs::File::open(config_file)
-        .unwrap()
-        .read_to_end(&mut config_data)
-        .unwrap();
-
-    let mut config: Config = toml::from_slice(config_data.as_slice()).unwrap();
+    let mut config = Config::load(&config_file)?;
 
     let mut launcher_config = match config.launcher {
-        None => return Err(format!("Launcher section is not present")),
+        None => return Err("Launcher section is not present".into()),
         Some(x) => x,
     };
 
-    if launcher_config.font == "" {
+    if launcher_config.font.is_empty() {
         // TODO
         launcher_config.font = String::from("sans");
     };
@@ -455,7 +451,14 @@ pub fn parse_menu(args: impl Iterator<Item = String>) -> Result<Config, String>
 
 pub fn get_options() -> Vec<String> {
     let stdin = io::stdin();
-    stdin.lock().lines().collect::<Result<_, _>>().unwrap()
+    match stdin.lock().lines().collect::<Result<_, _>>() {
+        Ok(options) => options,
+        Err(e) => {
+            error!("can't read options from stdin: {}", e);
+
+            process::exit(1);
+        }
+    }
 }
 
 fn main() {
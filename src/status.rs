@@ -0,0 +1,317 @@
+//! Status line of a command speaking the i3bar protocol, see i3bar-protocol(7)
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::io::{self, Write};
+use std::process::ChildStdin;
+
+/// Gap after a block if it doesn't set `separator_block_width`
+const BLOCK_GAP: u32 = 9;
+/// Click events waiting for a command which doesn't read them are dropped
+/// beyond this many bytes
+const MAX_UNSENT: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Protocol {
+    /// Nothing has been received yet
+    #[default]
+    Unknown,
+    /// Every line is the whole status
+    Text,
+    Json {
+        click_events: bool,
+    },
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(default)]
+    click_events: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Block {
+    pub full_text: String,
+    pub color: Option<String>,
+    pub min_width: Option<MinWidth>,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub urgent: bool,
+    pub name: Option<String>,
+    pub instance: Option<String>,
+    /// Draw a separator after the block, true by default
+    pub separator: Option<bool>,
+    pub separator_block_width: Option<u32>,
+}
+
+impl Block {
+    fn text(full_text: &str) -> Self {
+        Block {
+            full_text: full_text.to_owned(),
+            color: None,
+            min_width: None,
+            align: Align::Left,
+            urgent: false,
+            name: None,
+            instance: None,
+            separator: None,
+            separator_block_width: None,
+        }
+    }
+
+    /// Space between this block and the next one
+    pub fn gap(&self) -> u32 {
+        self.separator_block_width.unwrap_or(BLOCK_GAP)
+    }
+
+    pub fn has_separator(&self) -> bool {
+        self.separator.unwrap_or(true)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum MinWidth {
+    Pixels(u32),
+    /// As wide as this text
+    Text(String),
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Blocks received so far and the state of the protocol
+#[derive(Clone, Default)]
+pub struct Status {
+    protocol: Protocol,
+    pub blocks: Vec<Block>,
+}
+
+impl Status {
+    pub fn click_events(&self) -> bool {
+        self.protocol == Protocol::Json { click_events: true }
+    }
+
+    /// Handles a line printed by the command, returns whether the blocks have
+    /// changed
+    pub fn update(&mut self, line: &str) -> Result<bool, String> {
+        let mut blocks: Vec<Block> = match self.protocol {
+            Protocol::Unknown => {
+                // commands which don't start with a header print plain text
+                match serde_json::from_str::<Header>(line) {
+                    Ok(header) => {
+                        if header.version != 1 {
+                            return Err(format!("unsupported protocol version {}", header.version));
+                        }
+
+                        self.protocol = Protocol::Json {
+                            click_events: header.click_events,
+                        };
+                        return Ok(false);
+                    }
+                    Err(..) => {
+                        self.protocol = Protocol::Text;
+                        vec![Block::text(line)]
+                    }
+                }
+            }
+            Protocol::Text => vec![Block::text(line)],
+            Protocol::Json { .. } => {
+                // the status lines are elements of an endless array
+                let mut line = line.trim().trim_start_matches(',').trim_start();
+                if line.starts_with("[[") {
+                    line = &line[1..];
+                }
+
+                if line.is_empty() || line == "[" {
+                    return Ok(false);
+                }
+
+                serde_json::from_str(line).map_err(|e| format!("invalid status line: {}", e))?
+            }
+        };
+
+        // like i3bar, blocks without text are hidden
+        blocks.retain(|block| !block.full_text.is_empty());
+
+        if blocks == self.blocks {
+            return Ok(false);
+        }
+
+        self.blocks = blocks;
+        Ok(true)
+    }
+}
+
+/// Tap on a block, sent to the command if it has asked for click events
+#[derive(Serialize)]
+pub struct ClickEvent<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<&'a str>,
+    /// Mouse button like X numbers them: 1 for a tap, 3 for a long press,
+    /// 4 to 7 for swipes up, down, left and right, which scroll
+    pub button: u32,
+    pub x: i32,
+    pub y: i32,
+    pub relative_x: i32,
+    pub relative_y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Sends click events to the command, as elements of an endless array
+pub struct Clicks {
+    /// Non-blocking, so a command which doesn't read can't stall the bar
+    stdin: ChildStdin,
+    /// Whether an event has been sent already
+    started: bool,
+    /// Events the pipe hasn't taken yet, always whole ones
+    unsent: Vec<u8>,
+}
+
+impl Clicks {
+    pub fn new(stdin: ChildStdin) -> Self {
+        Clicks {
+            stdin,
+            started: false,
+            unsent: vec![],
+        }
+    }
+
+    /// Sends the event after those still waiting, or keeps it until the
+    /// next one if the pipe is full
+    pub fn send(&mut self, event: &ClickEvent) -> io::Result<()> {
+        let event = serde_json::to_string(event).map_err(io::Error::other)?;
+
+        if self.unsent.len() > MAX_UNSENT {
+            self.flush()?;
+
+            if self.unsent.len() > MAX_UNSENT {
+                return Err(io::Error::other(
+                    "the command doesn't read its click events",
+                ));
+            }
+        }
+
+        let prefix = match self.started {
+            true => ",",
+            false => "[\n",
+        };
+        self.started = true;
+
+        self.unsent
+            .extend_from_slice(format!("{}{}\n", prefix, event).as_bytes());
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            match self.stdin.write(&self.unsent) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.unsent.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(status: &Status) -> Vec<&str> {
+        status
+            .blocks
+            .iter()
+            .map(|block| block.full_text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut status = Status::default();
+
+        assert_eq!(
+            status.update(r#"{"version":1,"click_events":true}"#),
+            Ok(false)
+        );
+        assert!(status.click_events());
+        assert_eq!(status.update("["), Ok(false));
+
+        assert_eq!(
+            status.update(r#"[{"full_text":"a","urgent":true},{"full_text":""}]"#),
+            Ok(true)
+        );
+        assert_eq!(texts(&status), ["a"]);
+        assert!(status.blocks[0].urgent);
+
+        assert_eq!(
+            status.update(r#",[{"full_text":"b","align":"right"}]"#),
+            Ok(true)
+        );
+        assert_eq!(texts(&status), ["b"]);
+        assert_eq!(status.blocks[0].align, Align::Right);
+
+        // unchanged
+        assert_eq!(
+            status.update(r#",[{"full_text":"b","align":"right"}]"#),
+            Ok(false)
+        );
+        assert_eq!(status.update(""), Ok(false));
+        assert!(status.update(",[{").is_err());
+        assert_eq!(texts(&status), ["b"]);
+    }
+
+    #[test]
+    fn first_line_may_open_the_array() {
+        let mut status = Status::default();
+
+        assert_eq!(status.update(r#"{"version":1}"#), Ok(false));
+        assert!(!status.click_events());
+        assert_eq!(
+            status.update(r#"[[{"full_text":"a","min_width":"100%"}]"#),
+            Ok(true)
+        );
+        assert_eq!(
+            status.blocks[0].min_width,
+            Some(MinWidth::Text("100%".into()))
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut status = Status::default();
+
+        assert_eq!(
+            status.update(r#"{"version":2}"#),
+            Err("unsupported protocol version 2".to_owned())
+        );
+    }
+
+    #[test]
+    fn plain_text() {
+        let mut status = Status::default();
+
+        assert_eq!(status.update("12:00 [load 0.5]"), Ok(true));
+        assert_eq!(texts(&status), ["12:00 [load 0.5]"]);
+        assert_eq!(status.update("[{\"full_text\":\"a\"}]"), Ok(true));
+        assert_eq!(texts(&status), ["[{\"full_text\":\"a\"}]"]);
+        assert!(!status.click_events());
+    }
+}
@@ -0,0 +1,238 @@
+//! Workspaces from the sway IPC, see sway-ipc(7)
+
+use log::warn;
+use serde_derive::Deserialize;
+
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::os::unix::net::UnixStream;
+
+const MAGIC: &[u8] = b"i3-ipc";
+/// The magic string, the payload length and the message type
+const HEADER_LEN: usize = 14;
+
+/// Message types
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+/// Events have the highest bit set
+const WORKSPACE_EVENT: u32 = 0x8000_0000;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Workspace {
+    pub name: String,
+    pub focused: bool,
+    pub urgent: bool,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    success: bool,
+    error: Option<String>,
+}
+
+/// Connection to sway and the workspaces it has reported
+pub struct Sway {
+    stream: UnixStream,
+    /// Start of a message which hasn't been received completely
+    pending: Vec<u8>,
+    pub workspaces: Vec<Workspace>,
+}
+
+impl Sway {
+    /// Connects to the socket and asks for the workspaces and their changes,
+    /// returns another handle of the connection to read the replies from
+    pub fn connect(path: &str) -> io::Result<(Self, UnixStream)> {
+        let stream = UnixStream::connect(path)?;
+        let reader = stream.try_clone()?;
+
+        let mut sway = Sway {
+            stream,
+            pending: vec![],
+            workspaces: vec![],
+        };
+        sway.send(SUBSCRIBE, r#"["workspace"]"#)?;
+        sway.send(GET_WORKSPACES, "")?;
+
+        Ok((sway, reader))
+    }
+
+    fn send(&mut self, kind: u32, payload: &str) -> io::Result<()> {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+
+        self.stream.write_all(&message)
+    }
+
+    pub fn focus(&mut self, name: &str) -> io::Result<()> {
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        self.send(RUN_COMMAND, &format!("workspace \"{}\"", name))
+    }
+
+    /// Handles data received from sway, returns whether the workspaces have
+    /// changed
+    pub fn receive(&mut self, data: &[u8]) -> bool {
+        self.pending.extend_from_slice(data);
+
+        let mut changed = false;
+        while self.pending.len() >= HEADER_LEN {
+            if !self.pending.starts_with(MAGIC) {
+                warn!("invalid message from sway");
+                self.pending.clear();
+                break;
+            }
+
+            let len = u32::from_ne_bytes(self.pending[6..10].try_into().unwrap()) as usize;
+            let kind = u32::from_ne_bytes(self.pending[10..14].try_into().unwrap());
+            if self.pending.len() < HEADER_LEN + len {
+                break;
+            }
+
+            let payload: Vec<u8> = self.pending.drain(..HEADER_LEN + len).collect();
+            changed |= self.handle(kind, &payload[HEADER_LEN..]);
+        }
+
+        changed
+    }
+
+    fn handle(&mut self, kind: u32, payload: &[u8]) -> bool {
+        match kind {
+            GET_WORKSPACES => match serde_json::from_slice::<Vec<Workspace>>(payload) {
+                Ok(workspaces) if workspaces == self.workspaces => false,
+                Ok(workspaces) => {
+                    self.workspaces = workspaces;
+                    true
+                }
+                Err(e) => {
+                    warn!("invalid workspaces from sway: {}", e);
+                    false
+                }
+            },
+            // the event only describes the workspaces which have changed
+            WORKSPACE_EVENT => {
+                if let Err(e) = self.send(GET_WORKSPACES, "") {
+                    warn!("can't ask sway for the workspaces: {}", e);
+                }
+                false
+            }
+            RUN_COMMAND => {
+                for reply in serde_json::from_slice::<Vec<Reply>>(payload).unwrap_or_default() {
+                    if !reply.success {
+                        warn!("sway: {}", reply.error.unwrap_or_default());
+                    }
+                }
+                false
+            }
+            SUBSCRIBE => {
+                if let Ok(Reply { success: false, .. }) = serde_json::from_slice(payload) {
+                    warn!("can't subscribe to the workspace events of sway");
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+    use std::{env, fs, process};
+
+    fn message(kind: u32, payload: &str) -> Vec<u8> {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        message
+    }
+
+    /// Connects to a socket standing in for sway, returns the connection and
+    /// sway's end of it once the initial requests have arrived
+    fn connect(name: &str) -> (Sway, UnixStream) {
+        let path = env::temp_dir().join(format!("ppkui-sway-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (sway, _) = Sway::connect(path.to_str().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let _ = fs::remove_file(&path);
+
+        expect(&mut server, &message(SUBSCRIBE, r#"["workspace"]"#));
+        expect(&mut server, &message(GET_WORKSPACES, ""));
+
+        (sway, server)
+    }
+
+    fn expect(server: &mut UnixStream, expected: &[u8]) {
+        let mut received = vec![0; expected.len()];
+        server.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+
+    const WORKSPACES: &str = r#"[{"name":"1","focused":true,"urgent":false,"num":1},
+        {"name":"2","focused":false,"urgent":true,"num":2}]"#;
+
+    fn workspace(name: &str, focused: bool, urgent: bool) -> Workspace {
+        Workspace {
+            name: name.to_owned(),
+            focused,
+            urgent,
+        }
+    }
+
+    #[test]
+    fn reply_split_across_reads() {
+        let (mut sway, _server) = connect("split");
+        let reply = message(GET_WORKSPACES, WORKSPACES);
+
+        assert!(!sway.receive(&reply[..5]));
+        assert!(!sway.receive(&reply[5..20]));
+        assert!(sway.receive(&reply[20..]));
+        assert_eq!(
+            sway.workspaces,
+            [workspace("1", true, false), workspace("2", false, true)]
+        );
+
+        // the same workspaces again aren't a change
+        assert!(!sway.receive(&reply));
+    }
+
+    #[test]
+    fn two_messages_in_one_read() {
+        let (mut sway, _server) = connect("two");
+        let mut data = message(SUBSCRIBE, r#"{"success":true}"#);
+        data.extend(message(
+            GET_WORKSPACES,
+            r#"[{"name":"3","focused":true,"urgent":false}]"#,
+        ));
+
+        assert!(sway.receive(&data));
+        assert_eq!(sway.workspaces, [workspace("3", true, false)]);
+        assert!(sway.pending.is_empty());
+    }
+
+    #[test]
+    fn workspace_event_asks_for_the_workspaces() {
+        let (mut sway, mut server) = connect("event");
+
+        assert!(!sway.receive(&message(WORKSPACE_EVENT, r#"{"change":"focus"}"#)));
+        expect(&mut server, &message(GET_WORKSPACES, ""));
+    }
+
+    #[test]
+    fn focus_escapes_the_name() {
+        let (mut sway, mut server) = connect("focus");
+
+        sway.focus(r#"say "hi" \o/"#).unwrap();
+        expect(
+            &mut server,
+            &message(RUN_COMMAND, r#"workspace "say \"hi\" \\o/""#),
+        );
+    }
+}